@@ -0,0 +1,142 @@
+//! Answers from recent lookups, shared by every `resolve` in the process.
+//!
+//! Addresses are kept for as long as the resolvers said they stay valid, or
+//! `POSITIVE_TTL` when they did not say. Definite failures, where a resolver
+//! answered that the name does not exist, are kept for `NEGATIVE_TTL` so that
+//! repeated lookups of a dead link do not reach the network either. Transport
+//! and server errors are never cached. Answers are keyed by host and by the
+//! resolvers that produced them, since different resolvers may disagree.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tracing::trace;
+use utils::{Status, url::WebUrl};
+
+pub const POSITIVE_TTL: Duration = Duration::from_secs(300);
+pub const NEGATIVE_TTL: Duration = Duration::from_secs(30);
+const MAX_ENTRIES: usize = 1024;
+
+static ENTRIES: LazyLock<Mutex<HashMap<CacheKey, CacheEntry>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub host: String,
+    pub providers: Vec<String>,
+    pub cachers: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    /// `None` for a negative answer.
+    pub address: Option<String>,
+    pub status: Status,
+    pub expires_at: Instant,
+}
+
+impl CacheEntry {
+    pub fn is_negative(&self) -> bool {
+        self.address.is_none()
+    }
+
+    /// Time left before the entry expires.
+    pub fn ttl(&self) -> Duration {
+        self.expires_at.saturating_duration_since(Instant::now())
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at <= Instant::now()
+    }
+}
+
+/// Whether a failed lookup ending in `status` is worth remembering.
+fn is_negative_answer(status: Status) -> bool {
+    matches!(
+        status,
+        Status::NotFound | Status::Gone | Status::Misdirected
+    )
+}
+
+pub(crate) fn get(key: &CacheKey) -> Option<CacheEntry> {
+    let mut entries = ENTRIES.lock().unwrap_or_else(PoisonError::into_inner);
+    match entries.get(key) {
+        Some(entry) if entry.is_expired() => {
+            entries.remove(key);
+            None
+        }
+        entry => entry.cloned(),
+    }
+}
+
+/// Remembers the outcome of a lookup, if it is one worth remembering, for the
+/// `ttl` seconds its resolvers gave or else the default for its kind. Returns
+/// how long it will be remembered.
+pub(crate) fn insert(
+    key: CacheKey,
+    address: Option<String>,
+    status: Status,
+    ttl: Option<u32>,
+) -> Duration {
+    let ttl = match address {
+        Some(_) => ttl.map_or(POSITIVE_TTL, |ttl| Duration::from_secs(ttl.into())),
+        None if is_negative_answer(status) => NEGATIVE_TTL,
+        None => return Duration::ZERO,
+    };
+    if ttl.is_zero() {
+        return ttl;
+    }
+    let mut entries = ENTRIES.lock().unwrap_or_else(PoisonError::into_inner);
+    if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+        entries.retain(|_, entry| !entry.is_expired());
+        if entries.len() >= MAX_ENTRIES
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(key, _)| key.clone())
+        {
+            entries.remove(&oldest);
+        }
+    }
+    trace!("Caching {:?} for {}s", key.host, ttl.as_secs());
+    entries.insert(
+        key,
+        CacheEntry {
+            address,
+            status,
+            expires_at: Instant::now() + ttl,
+        },
+    );
+    ttl
+}
+
+/// Snapshot of every unexpired entry.
+pub fn entries() -> Vec<(CacheKey, CacheEntry)> {
+    let mut entries = ENTRIES.lock().unwrap_or_else(PoisonError::into_inner);
+    entries.retain(|_, entry| !entry.is_expired());
+    entries
+        .iter()
+        .map(|(key, entry)| (key.clone(), entry.clone()))
+        .collect()
+}
+
+/// Drops every entry for `host`, whichever resolvers produced it. `host` may
+/// also be a whole URL. Returns how many entries were dropped.
+pub fn flush_host(host: &str) -> usize {
+    let host = host
+        .parse::<WebUrl>()
+        .map_or_else(|_| host.to_owned(), |url| url.host().to_string());
+    let mut entries = ENTRIES.lock().unwrap_or_else(PoisonError::into_inner);
+    let before = entries.len();
+    entries.retain(|key, _| key.host != host);
+    before - entries.len()
+}
+
+/// Drops every entry.
+pub fn flush() {
+    ENTRIES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
@@ -0,0 +1,115 @@
+//! Following a name from resolver to resolver.
+//!
+//! A lookup starts at one resolver and may be sent on by redirects and
+//! delegations until some resolver answers. `follow` drives that walk for
+//! every kind of resolver: each step either answers or names the next
+//! resolvers to ask. When there are several, such as the secondaries of a
+//! delegation, they are tried in order, passing over those that are down or
+//! do not answer. A walk that asks the same resolver about the same name
+//! twice, or takes more than `MAX_HOPS` steps, ends in `LoopDetected`.
+//! Every exchange is recorded as a `Hop`.
+
+use crate::{Lookup, health, resolution::Hop, resolvers::is_unreachable};
+use std::{collections::HashSet, time::Instant};
+use tracing::{error, trace, warn};
+use utils::Status;
+
+const MAX_HOPS: usize = 16;
+
+/// A lookup together with the exchanges that led to it.
+pub(crate) type Traced = (Lookup, Vec<Hop>);
+
+/// Outcome of asking one resolver.
+pub(crate) enum Step {
+    /// The walk ends here.
+    Done(Lookup),
+    /// Ask the first of `servers` that answers about `name` next, as told
+    /// with `status`. An answer found this way lasts no longer than `ttl`, if
+    /// given.
+    Next {
+        servers: Vec<String>,
+        name: String,
+        status: Status,
+        ttl: Option<u32>,
+    },
+}
+
+/// Asks `server` about `name` with `step`, then follows whatever it says.
+pub(crate) async fn follow<F, Fut>(server: &str, name: &str, step: F) -> Traced
+where
+    F: Fn(String, String) -> Fut,
+    Fut: Future<Output = Step>,
+{
+    let (mut servers, mut name) = (vec![server.to_owned()], name.to_owned());
+    let mut visited = HashSet::new();
+    let mut hops = Vec::new();
+    let mut ttl = None;
+    'walk: loop {
+        let mut candidates = health::usable(&servers).into_iter().peekable();
+        while let Some(server) = candidates.next() {
+            if visited.len() >= MAX_HOPS {
+                error!(
+                    "Gave up on {} at {} after {} hops. Please notify DNS provider of misconfiguration.",
+                    name, server, MAX_HOPS
+                );
+                return ((None, Status::LoopDetected, None), hops);
+            }
+            if !visited.insert((server.clone(), name.clone())) {
+                error!(
+                    "Redirection has looped back to {} for {}. Please notify DNS provider of misconfiguration.",
+                    server, name
+                );
+                return ((None, Status::LoopDetected, None), hops);
+            }
+            let started = Instant::now();
+            let outcome = step(server.clone(), name.clone()).await;
+            let elapsed = started.elapsed();
+            match outcome {
+                Step::Done((address, status, last_ttl)) => {
+                    hops.push(Hop {
+                        server: server.clone(),
+                        name: name.clone(),
+                        status,
+                        address: address.clone(),
+                        ttl: last_ttl,
+                        elapsed,
+                    });
+                    if is_unreachable(status) && candidates.peek().is_some() {
+                        warn!("{} did not answer about {}, trying the next", server, name);
+                        continue;
+                    }
+                    return ((address, status, min_ttl(ttl, last_ttl)), hops);
+                }
+                Step::Next {
+                    servers: next,
+                    name: next_name,
+                    status,
+                    ttl: hop_ttl,
+                } => {
+                    let next_address = next.join(",");
+                    trace!("Asking {} about {}", next_address, next_name);
+                    hops.push(Hop {
+                        server,
+                        name,
+                        status,
+                        address: Some(next_address),
+                        ttl: hop_ttl,
+                        elapsed,
+                    });
+                    ttl = min_ttl(ttl, hop_ttl);
+                    (servers, name) = (next, next_name);
+                    continue 'walk;
+                }
+            }
+        }
+        // Only reached when there was nobody to ask at all.
+        return ((None, Status::HostUnreachable, None), hops);
+    }
+}
+
+fn min_ttl(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
@@ -0,0 +1,79 @@
+//! Which resolvers have been failing lately.
+//!
+//! An endpoint that fails `FAILURE_THRESHOLD` exchanges in a row is marked
+//! down for `BACKOFF`, and `resolve` passes it over while others are up. The
+//! first exchange after the backoff decides whether it stays down.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+const FAILURE_THRESHOLD: u32 = 3;
+const BACKOFF: Duration = Duration::from_secs(30);
+
+static ENDPOINTS: LazyLock<Mutex<HashMap<String, Health>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Health {
+    /// Exchanges failed since the last one that succeeded.
+    pub failures: u32,
+    pub down_until: Option<Instant>,
+}
+
+impl Health {
+    pub fn is_down(&self) -> bool {
+        self.down_until.is_some_and(|until| until > Instant::now())
+    }
+}
+
+pub(crate) fn record_success(address: &str) {
+    let mut endpoints = ENDPOINTS.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(health) = endpoints.remove(address)
+        && health.down_until.is_some()
+    {
+        info!("{} is answering again", address);
+    }
+}
+
+pub(crate) fn record_failure(address: &str) {
+    let mut endpoints = ENDPOINTS.lock().unwrap_or_else(PoisonError::into_inner);
+    let health = endpoints.entry(address.to_owned()).or_default();
+    health.failures += 1;
+    if health.failures >= FAILURE_THRESHOLD && !health.is_down() {
+        warn!(
+            "{} failed {} times in a row, skipping it for {}s",
+            address,
+            health.failures,
+            BACKOFF.as_secs()
+        );
+        health.down_until = Some(Instant::now() + BACKOFF);
+    }
+}
+
+/// The endpoints that are not down, in order, or all of them if every one is.
+pub(crate) fn usable(endpoints: &[String]) -> Vec<String> {
+    let health = ENDPOINTS.lock().unwrap_or_else(PoisonError::into_inner);
+    let up = endpoints
+        .iter()
+        .filter(|address| !health.get(*address).is_some_and(Health::is_down))
+        .cloned()
+        .collect::<Vec<_>>();
+    if up.is_empty() {
+        endpoints.to_vec()
+    } else {
+        up
+    }
+}
+
+/// Snapshot of every endpoint that has failed since it last succeeded.
+pub fn failing() -> Vec<(String, Health)> {
+    ENDPOINTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(address, health)| (address.clone(), *health))
+        .collect()
+}
@@ -1,114 +1,208 @@
-use async_std::task;
-use futures::{FutureExt, select};
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
-use std::{
-    cmp::Ordering,
-    env,
-    net::TcpStream,
-    path::{self, PathBuf},
+use async_std::future;
+use cache::CacheKey;
+use chain::{Step, Traced};
+use futures::{
+    FutureExt,
+    future::{BoxFuture, Fuse},
+    select,
 };
+use futures_rustls::TlsConnector;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, info, trace, warn};
-use utils::{fqdn_to_upe, get_config_dir, receive_data, send_data, sql_cols, status};
+use utils::{
+    Status,
+    handshake::Capabilities,
+    protocol::{cacher, provider},
+    url::WebUrl,
+};
 
-const DNS_IP: &str = "0.0.0.0:6202";
-const CACHER_IP: &str = "0.0.0.0:6203";
+pub mod cache;
+mod chain;
+pub mod health;
+mod pool;
+mod resolution;
+mod resolvers;
+mod stacks;
 
-pub async fn resolve(
-    dest_addr: &str,
-    integrity_check: Option<bool>,
-    dns_ip: Option<&str>,
-    cacher_ip: Option<&str>,
-) -> (String, u32) {
-    let program_version: Vec<u32> = env!("CARGO_PKG_VERSION")
-        .split('.')
-        .map(|f| match f.parse::<u32>() {
-            Ok(version) => version,
-            Err(e) => {
-                panic!("Failed to parse version: {e}");
-            }
-        })
-        .collect();
-    assert!(program_version.len() > 2);
-    let dns_ip = dns_ip.unwrap_or(DNS_IP).to_owned();
-    let cacher_ip = cacher_ip.unwrap_or(CACHER_IP).to_owned();
-    let integrity_check = integrity_check.unwrap_or(false);
-    let mut result = (String::new(), status::HOST_UNREACHABLE);
+pub use pool::{HOP_TIMEOUT, close_idle_connections};
+pub use resolution::{Hop, Resolution, Source};
+pub use resolvers::{ResolverConfig, Strategy};
+pub use stacks::get_stack_info;
+#[cfg(feature = "gtk")]
+pub use stacks::parse_stack;
+
+/// Outcome of asking one resolver: the address, if any, the final status and
+/// how many seconds the answer stays valid, if the resolver said.
+pub type Lookup = (Option<String>, Status, Option<u32>);
+
+/// Resolves the host of `dest_addr` to a server address, racing the DNS
+/// cachers against the DNS providers in `resolvers`. Answers are served from
+/// `cache` while fresh, except when an integrity check asks for both kinds of
+/// resolver to be compared.
+///
+/// Gives up with `Status::TimedOut` once `resolvers.timeout` has passed.
+/// Dropping the returned future cancels every exchange still in flight.
+pub async fn resolve(dest_addr: &str, resolvers: &ResolverConfig) -> Resolution {
+    let deadline = Instant::now() + resolvers.timeout;
+    let integrity_check = resolvers.integrity_check;
+    let cache_key = dest_addr.parse::<WebUrl>().ok().map(|url| CacheKey {
+        host: url.host().to_string(),
+        providers: resolvers.providers.clone(),
+        cachers: resolvers.cachers.clone(),
+    });
+    if let Some(key) = &cache_key
+        && !integrity_check
+        && let Some(entry) = cache::get(key)
+    {
+        debug!("Answering {} from cache", key.host);
+        return Resolution {
+            ttl: entry.ttl(),
+            address: entry.address,
+            status: entry.status,
+            source: Some(Source::Memory),
+            hops: Vec::new(),
+        };
+    }
+    let (cachers, strategy) = (resolvers.cachers.clone(), resolvers.strategy);
     let dest_addr_clone = dest_addr.to_owned();
-    let mut cache_handle =
-        task::spawn(async move { cache_task(&cacher_ip, &dest_addr_clone).await }).fuse();
+    let tls = resolvers.tls.clone();
+    let mut cache_handle = async move {
+        resolvers::query(&cachers, strategy, |cacher_ip| {
+            let dest_addr = dest_addr_clone.clone();
+            let tls = tls.clone();
+            async move { cache_walk(&cacher_ip, &dest_addr, tls.as_ref()).await }
+        })
+        .await
+    }
+    .boxed()
+    .fuse();
+    let providers = resolvers.providers.clone();
     let dest_addr_clone = dest_addr.to_owned();
-    let mut dns_handle =
-        task::spawn(async move { dns_task(&dns_ip, &dest_addr_clone).await }).fuse();
+    let tls = resolvers.tls.clone();
+    let mut dns_handle = async move {
+        resolvers::query(&providers, strategy, |dns_ip| {
+            let dest_addr = dest_addr_clone.clone();
+            let tls = tls.clone();
+            async move { dns_walk(&dns_ip, &dest_addr, tls.as_ref()).await }
+        })
+        .await
+    }
+    .boxed()
+    .fuse();
     let mut comparison = None;
-    let data = select! {
-        result = cache_handle => {
-            let mut return_data = (None, result.1);
-            match result.0 {
-                Some(address) => {
-                    info!("Cache handle returned first");
-                    return_data.0 = Some(address.clone());
-                    if integrity_check {comparison = Some(task::spawn(compare_results(address, dns_handle)));}
-                }
-                None => {
-                    warn!("Cache handle returned None! Fallback to DNS handle.");
-                    let dns_res = dns_handle.await;
-                    if dns_res.0.is_some() {
-                        return_data = dns_res;
+    let race = async {
+        // With only one kind of resolver there is nothing to race.
+        if resolvers.providers.is_empty() {
+            let (result, hops) = cache_handle.await;
+            return (result, Source::Cacher, hops);
+        }
+        if resolvers.cachers.is_empty() {
+            let (result, hops) = dns_handle.await;
+            return (result, Source::Provider, hops);
+        }
+        select! {
+            (result, mut hops) = cache_handle => {
+                match &result.0 {
+                    Some(address) => {
+                        info!("Cache handle returned first");
+                        if integrity_check {comparison = Some(compare_results(address.clone(), dns_handle, cache_key.clone(), resolvers.tls.as_ref()));}
+                        (result, Source::Cacher, hops)
                     }
-                    else {
-                        warn!("Unable to resolve {}!", dest_addr);
+                    None => {
+                        warn!("Cache handle returned None! Fallback to DNS handle.");
+                        let (dns_res, dns_hops) = dns_handle.await;
+                        hops.extend(dns_hops);
+                        if dns_res.0.is_none() {
+                            warn!("Unable to resolve {}!", dest_addr);
+                        }
+                        // The providers' reason outranks the cachers'.
+                        (dns_res, Source::Provider, hops)
                     }
                 }
             }
-            return_data
-        }
-        result = dns_handle => {
-            let mut return_data = (None, result.1);
-            match result.0 {
-                Some(address) => {
-                    info!("DNS handle returned first");
-                    return_data.0 = Some(address.clone());
-                    if integrity_check {comparison = Some(task::spawn(compare_results(address, cache_handle)));}
-                }
-                None => {
-                    warn!("DNS handle returned None! Fallback to cache handle.");
-                    let cache_res = cache_handle.await;
-                    if cache_res.0.is_some() {
-                        return_data = cache_res;
+            (result, mut hops) = dns_handle => {
+                match &result.0 {
+                    Some(address) => {
+                        info!("DNS handle returned first");
+                        if integrity_check {comparison = Some(compare_results(address.clone(), cache_handle, cache_key.clone(), resolvers.tls.as_ref()));}
+                        (result, Source::Provider, hops)
                     }
-                    else {
-                        warn!("Unable to resolve {}!", dest_addr);
+                    None => {
+                        warn!("DNS handle returned None! Fallback to cache handle.");
+                        let (cache_res, cache_hops) = cache_handle.await;
+                        hops.extend(cache_hops);
+                        if cache_res.0.is_some() {
+                            (cache_res, Source::Cacher, hops)
+                        }
+                        else {
+                            warn!("Unable to resolve {}!", dest_addr);
+                            (result, Source::Provider, hops)
+                        }
                     }
                 }
             }
-            return_data
         }
     };
-    match data.0 {
-        Some(response) => {
-            result = (response, data.1);
+    let remaining = || deadline.saturating_duration_since(Instant::now());
+    let ((address, status, ttl), source, hops) = match future::timeout(remaining(), race).await {
+        Ok((lookup, source, hops)) => (lookup, Some(source), hops),
+        Err(_) => {
+            warn!(
+                "Resolving {} took longer than {}s",
+                dest_addr,
+                resolvers.timeout.as_secs()
+            );
+            ((None, Status::TimedOut, None), None, Vec::new())
         }
-        None => error!("Unable to resolve {}.", dest_addr),
+    };
+    let ttl = match &cache_key {
+        Some(key) => cache::insert(key.clone(), address.clone(), status, ttl),
+        None => Duration::ZERO,
+    };
+    if address.is_none() {
+        error!("Unable to resolve {}.", dest_addr);
     }
+    let mut resolution = Resolution {
+        address,
+        status,
+        ttl,
+        source,
+        hops,
+    };
     if let Some(comparison) = comparison
         && integrity_check
+        && !future::timeout(remaining(), comparison)
+            .await
+            .unwrap_or(true)
+        && let Some(key) = &cache_key
     {
-        comparison.await;
+        cache::flush_host(&key.host);
+        resolution.ttl = Duration::ZERO;
     }
-    result
+    resolution
 }
 
-pub async fn dns_task(dns_ip: &str, dest_addr: &str) -> (Option<String>, u32) {
-    let (dest_url, _, _) = fqdn_to_upe(dest_addr);
+/// Resolves `dest_addr` through the DNS provider chain rooted at `dns_ip`,
+/// connecting over TLS through `tls` when given.
+pub async fn dns_task(dns_ip: &str, dest_addr: &str, tls: Option<&TlsConnector>) -> Lookup {
+    dns_walk(dns_ip, dest_addr, tls).await.0
+}
+
+async fn dns_walk(dns_ip: &str, dest_addr: &str, tls: Option<&TlsConnector>) -> Traced {
+    let dest_url = match dest_addr.parse::<WebUrl>() {
+        Ok(url) => url.host().to_string(),
+        Err(e) => {
+            warn!("Cannot resolve {}: {}", dest_addr, e);
+            return ((None, Status::BadRequest, None), Vec::new());
+        }
+    };
     if dns_ip != String::new() {
         trace!("Attempting to resolve DNS Server {}", dns_ip);
-        let Ok(stream) = TcpStream::connect(dns_ip) else {
-            warn!("Failed to resolve to DNS Server {}!", dns_ip);
-            return (None, status::HOST_UNREACHABLE);
-        };
-        info!("Connected to {}", dns_ip);
         debug!("Attempting to resolve {}", dest_url);
-        let dest = dns_resolve(&stream, &dest_url, "", dns_ip, &["".to_string()]);
+        let (dest, hops) = chain::follow(dns_ip, &dest_url, |dns_ip, destination| async move {
+            dns_step(&dns_ip, &destination, tls).await
+        })
+        .await;
         info!(
             "Resolved {} to {}!",
             dest_url,
@@ -116,96 +210,81 @@ pub async fn dns_task(dns_ip: &str, dest_addr: &str) -> (Option<String>, u32) {
         );
         if let Some(dest_ip) = dest.0 {
             if dest_ip == String::new() {
-                return (None, dest.1);
+                return ((None, dest.1, dest.2), hops);
             } else {
-                return (Some(dest_ip), dest.1);
+                return ((Some(dest_ip), dest.1, dest.2), hops);
             };
         }
+        return ((None, dest.1, dest.2), hops);
     }
-    (None, status::HOST_UNREACHABLE)
+    ((None, Status::HostUnreachable, None), Vec::new())
 }
 
-fn dns_resolve(
-    stream: &TcpStream,
-    destination: &str,
-    prev: &str,
-    dns_ip: &str,
-    routes: &[String],
-) -> (Option<String>, u32) {
+/// Asks the DNS provider at `dns_ip` about the last block of `destination`.
+async fn dns_step(dns_ip: &str, destination: &str, tls: Option<&TlsConnector>) -> Step {
     let block = destination.split('.').next_back().unwrap_or_default();
-    let next_prev = ".".to_owned() + block + prev;
     let is_last_block = block == destination;
-    let program_version: Vec<u32> = env!("CARGO_PKG_VERSION")
-        .split('.')
-        .map(|f| match f.parse::<u32>() {
-            Ok(version) => version,
-            Err(e) => {
-                panic!("Failed to parse version: {e}");
-            }
-        })
-        .collect();
-    assert!(program_version.len() > 2);
-    let mut payload = program_version[0].to_le_bytes().to_vec();
-    payload.extend_from_slice(&program_version[1].to_le_bytes());
-    payload.extend_from_slice(&program_version[2].to_le_bytes());
-    payload.extend_from_slice(if is_last_block { &[0u8] } else { &[1u8] });
-    payload.extend_from_slice(block.as_bytes());
-    send_data(&payload, stream);
-    let response = receive_data(stream);
-    match response.len().cmp(&4) {
-        Ordering::Less => {
-            error!("Server send an invalid response.");
-            return (None, status::BAD_RESPONSE);
+    let request = provider::Request::Resolve {
+        last_block: is_last_block,
+        block: block.to_owned(),
+    };
+    let request = match request.encode() {
+        Ok(request) => request,
+        Err(e) => {
+            error!("Cannot ask DNS Server {} about {}: {}", dns_ip, block, e);
+            return Step::Done((None, Status::BadRequest, None));
         }
-        Ordering::Equal => {
-            return (None, u32::from_le_bytes(response.try_into().unwrap()));
+    };
+    let (response, with_ttl) = match pool::exchange(dns_ip, &request, tls).await {
+        Ok((response, capabilities)) => (response, capabilities.contains(Capabilities::TTL)),
+        Err(e) => {
+            error!("Exchange with DNS Server {} failed: {}", dns_ip, e);
+            return Step::Done((None, e.status(), None));
         }
-        _ => {}
-    }
-    let statuscode = u32::from_le_bytes(response[0..4].try_into().unwrap());
+    };
+    let (statuscode, fqdn, ttl) = match provider::Response::decode(&response, with_ttl) {
+        Ok(provider::Response::Address {
+            status,
+            address,
+            ttl,
+        }) => (status, address, ttl),
+        Ok(provider::Response::Status(status)) => return Step::Done((None, status, None)),
+        Err(e) => {
+            error!("Server send an invalid response: {}", e);
+            return Step::Done((None, Status::BadResponse, None));
+        }
+    };
     match statuscode {
-        status::SUCCESS => {
-            let fqdn = String::from_utf8_lossy(&response[4..]);
+        Status::Success => {
             if !is_last_block {
                 warn!("DNS resolved to destination {} early.", fqdn);
             }
-            return (Some(fqdn.into_owned()), statuscode);
+            return Step::Done((Some(fqdn), statuscode, ttl));
         }
-        status::NON_AUTHORITATIVE => {
-            let fqdn = String::from_utf8_lossy(&response[4..]);
+        Status::NonAuthoritative => {
             warn!(
                 "DNS fallback configured to correct FQN {} where doesn't exist.",
                 fqdn
             );
-            return (Some(fqdn.into_owned()), statuscode);
+            return Step::Done((Some(fqdn), statuscode, ttl));
         }
-        status::PERMANENT_REDIRECT => {
-            let fqdn = String::from_utf8_lossy(&response[4..]);
+        Status::PermanentRedirect => {
             warn!("DNS Server {} has moved to {}!", dns_ip, fqdn);
-            trace!("Attempting to connect to new DNS Server {}", fqdn);
-            let Ok(newstream) = TcpStream::connect(fqdn.to_string()) else {
-                error!("Failed to resolve new DNS Server {}!", fqdn);
-                return (None, statuscode);
+            trace!("Attempting to resolve through new DNS Server {}", fqdn);
+            return Step::Next {
+                servers: delegates(&fqdn),
+                name: destination.to_owned(),
+                status: statuscode,
+                ttl: None,
             };
-            info!("Connected to {}", fqdn);
-            return dns_resolve(&newstream, destination, prev, &fqdn, routes);
         }
-        status::FOUND => {
-            let fqdn = String::from_utf8_lossy(&response[4..]);
+        Status::Found => {
             if is_last_block {
                 warn!(
                     "End of client chain reached, but server returned {} as DNS.",
                     fqdn
                 );
-                if routes.contains(&fqdn.to_string()) {
-                    error!(
-                        "DNS redirection has looped. Please notify DNS provider of misconfiguration."
-                    );
-                    return (None, status::LOOP_DETECTED);
-                }
             }
-            let mut routes = routes.to_vec();
-            routes.push(fqdn.to_string());
             trace!("Attempting to resolve intermediary DNS Server {}", &fqdn);
             let newdestination = if !is_last_block {
                 destination
@@ -220,22 +299,26 @@ fn dns_resolve(
                 destination.to_string()
             };
             debug!("Passing {} to {}", newdestination, &fqdn);
-            let Ok(newstream) = TcpStream::connect(fqdn.to_string()) else {
-                error!("Failed to resolve intermediary DNS {}!", fqdn);
-                return (None, statuscode);
+            // An answer found through a delegation lasts no longer than it.
+            return Step::Next {
+                servers: delegates(&fqdn),
+                name: newdestination,
+                status: statuscode,
+                ttl,
             };
-            debug!("Attempting to resolve {}", newdestination);
-            return dns_resolve(&newstream, &newdestination, &next_prev, &fqdn, &routes);
         }
-        status::GONE => {
-            let fqdn = String::from_utf8_lossy(&response[4..]);
+        Status::Gone => {
             if !is_last_block {
                 warn!("Reached end of DNS chain early! Rectifying FQN as {}", fqdn);
             }
-            return (Some(fqdn.into_owned()), statuscode);
+            return Step::Done((Some(fqdn), statuscode, ttl));
         }
-        status::MISDIRECTED => {
-            error!("DNS Server couldn't resolve {}.", next_prev);
+        Status::Misdirected => {
+            error!("DNS Server {} couldn't resolve {}.", dns_ip, block);
+        }
+        status if status.is_client_error() || status.is_server_error() => {
+            error!("DNS Server {} refused {}: {}", dns_ip, destination, status);
+            return Step::Done((None, status, None));
         }
         _ => {
             error!(
@@ -244,20 +327,31 @@ fn dns_resolve(
             );
         }
     }
-    (None, status::HOST_UNREACHABLE)
+    Step::Done((None, Status::HostUnreachable, None))
+}
+
+/// The DNS providers in a delegation, which lists any secondaries after the
+/// first separated by commas.
+fn delegates(fqdn: &str) -> Vec<String> {
+    fqdn.split(',').map(str::to_owned).collect()
 }
 
-async fn cache_task(cacher_ip: &str, dest_addr: &str) -> (Option<String>, u32) {
-    let (dest_url, _, _) = fqdn_to_upe(dest_addr);
+async fn cache_walk(cacher_ip: &str, dest_addr: &str, tls: Option<&TlsConnector>) -> Traced {
+    let dest_url = match dest_addr.parse::<WebUrl>() {
+        Ok(url) => url.host().to_string(),
+        Err(e) => {
+            warn!("Cannot resolve {}: {}", dest_addr, e);
+            return ((None, Status::BadRequest, None), Vec::new());
+        }
+    };
     if cacher_ip != String::new() {
         trace!("Contacting DNS Cacher {}", cacher_ip);
-        let Ok(stream) = TcpStream::connect(cacher_ip) else {
-            warn!("Failed to contact DNS Cacher {}!", cacher_ip);
-            return (None, status::HOST_UNREACHABLE);
-        };
-        info!("Connected to {}", cacher_ip);
         debug!("Locating {}", dest_url);
-        let dest = cache_resolve(&stream, &dest_url, cacher_ip);
+        let (dest, hops) =
+            chain::follow(cacher_ip, &dest_url, |cacher_ip, destination| async move {
+                cache_step(&cacher_ip, &destination, tls).await
+            })
+            .await;
         info!(
             "Resolved {} to {}!",
             dest_url,
@@ -265,62 +359,61 @@ async fn cache_task(cacher_ip: &str, dest_addr: &str) -> (Option<String>, u32) {
         );
         if let Some(dest_ip) = dest.0 {
             if dest_ip == String::new() {
-                return (None, dest.1);
+                return ((None, dest.1, dest.2), hops);
             } else {
-                return (Some(dest_ip), dest.1);
+                return ((Some(dest_ip), dest.1, dest.2), hops);
             };
         }
+        return ((None, dest.1, dest.2), hops);
     }
-    (None, status::HOST_UNREACHABLE)
+    ((None, Status::HostUnreachable, None), Vec::new())
 }
 
-fn cache_resolve(stream: &TcpStream, destination: &str, dns_ip: &str) -> (Option<String>, u32) {
-    let program_version: Vec<u32> = env!("CARGO_PKG_VERSION")
-        .split('.')
-        .map(|f| match f.parse::<u32>() {
-            Ok(version) => version,
-            Err(e) => {
-                panic!("Failed to parse version: {e}");
-            }
-        })
-        .collect();
-    assert!(program_version.len() > 2);
-    let mut payload = program_version[0].to_le_bytes().to_vec();
-    payload.extend_from_slice(&program_version[1].to_le_bytes());
-    payload.extend_from_slice(&program_version[2].to_le_bytes());
-    payload.extend_from_slice(destination.as_bytes());
-    send_data(&payload, stream);
-    let response = receive_data(stream);
-    match response.len().cmp(&4) {
-        Ordering::Less => {
-            error!("Server send an invalid response.");
-            return (None, status::BAD_RESPONSE);
+/// Asks the DNS cacher at `dns_ip` about `destination`.
+async fn cache_step(dns_ip: &str, destination: &str, tls: Option<&TlsConnector>) -> Step {
+    let request = cacher::Request::Resolve {
+        fqdn: destination.to_owned(),
+    };
+    let (response, with_ttl) = match pool::exchange(dns_ip, &request.encode(), tls).await {
+        Ok((response, capabilities)) => (response, capabilities.contains(Capabilities::TTL)),
+        Err(e) => {
+            error!("Exchange with DNS Cacher {} failed: {}", dns_ip, e);
+            return Step::Done((None, e.status(), None));
         }
-        Ordering::Equal => {
-            return (None, u32::from_le_bytes(response.try_into().unwrap()));
+    };
+    let (statuscode, fqdn, ttl) = match cacher::Response::decode(&response, with_ttl) {
+        Ok(cacher::Response::Address {
+            status,
+            address,
+            ttl,
+        }) => (status, address, ttl),
+        Ok(cacher::Response::Status(status)) => return Step::Done((None, status, None)),
+        Err(e) => {
+            error!("Server send an invalid response: {}", e);
+            return Step::Done((None, Status::BadResponse, None));
         }
-        _ => {}
-    }
-    let statuscode = u32::from_le_bytes(response[0..4].try_into().unwrap());
+    };
     match statuscode {
-        status::SUCCESS => {
-            let fqdn = String::from_utf8_lossy(&response[4..]);
-            return (Some(fqdn.into_owned()), statuscode);
+        Status::Success => {
+            return Step::Done((Some(fqdn), statuscode, ttl));
         }
-        status::PERMANENT_REDIRECT => {
-            let fqdn = String::from_utf8_lossy(&response[4..]);
+        Status::PermanentRedirect => {
             warn!("DNS Cacher {} has moved to {}!", dns_ip, fqdn);
-            trace!("Attempting to connect to new DNS Cacher {}", fqdn);
-            let Ok(newstream) = TcpStream::connect(fqdn.to_string()) else {
-                error!("Failed to resolve new DNS Cacher {}!", fqdn);
-                return (None, statuscode);
+            trace!("Attempting to contact new DNS Cacher {}", fqdn);
+            return Step::Next {
+                servers: vec![fqdn],
+                name: destination.to_owned(),
+                status: statuscode,
+                ttl: None,
             };
-            info!("Connected to {}", fqdn);
-            return cache_resolve(&newstream, destination, &fqdn);
         }
-        status::MISDIRECTED => {
+        Status::Misdirected => {
             error!("DNS Cacher couldn't resolve {}.", destination);
         }
+        status if status.is_client_error() || status.is_server_error() => {
+            error!("DNS Cacher {} refused {}: {}", dns_ip, destination, status);
+            return Step::Done((None, status, None));
+        }
         _ => {
             error!(
                 "DNS Cacher failure. Are you sure {} is a DNS Cacher?",
@@ -328,75 +421,54 @@ fn cache_resolve(stream: &TcpStream, destination: &str, dns_ip: &str) -> (Option
             );
         }
     }
-    (None, status::HOST_UNREACHABLE)
+    Step::Done((None, Status::HostUnreachable, None))
 }
 
+/// Waits for the slower resolver and compares its answer with `complete`.
+/// Returns whether they agree; if not, the DNS cachers are asked to recheck.
 async fn compare_results(
     complete: String,
-    future: futures::future::Fuse<task::JoinHandle<(Option<String>, u32)>>,
-) {
-    let result = future.await.0;
-    if result.is_some() && result.unwrap() != complete {
-        error!("DNS Server and DNS Cacher returned different results!");
-        //report to DNS cacher that its information is outdated
+    future: Fuse<BoxFuture<'static, Traced>>,
+    key: Option<CacheKey>,
+    tls: Option<&TlsConnector>,
+) -> bool {
+    let Some(result) = future.await.0.0 else {
+        return true;
+    };
+    if result == complete {
+        return true;
     }
+    error!("DNS Server and DNS Cacher returned different results!");
+    if let Some(key) = key {
+        for cacher_ip in key.cachers.iter().filter(|cacher_ip| !cacher_ip.is_empty()) {
+            report_outdated(cacher_ip, &key.host, tls).await;
+        }
+    }
+    false
 }
 
-pub async fn parse_stack(elements: &str, stack: &str, applet: &str) -> Option<gtk::Box> {
-    let config_dir = match get_config_dir(applet) {
-        Some(dir) => dir,
-        None => return None,
+/// Asks the DNS cacher to check its entry for `fqdn` against the DNS provider.
+async fn report_outdated(cacher_ip: &str, fqdn: &str, tls: Option<&TlsConnector>) {
+    let request = cacher::Request::Invalidate {
+        fqdn: fqdn.to_owned(),
     };
-    let dbpath = config_dir.join(path::Path::new("stacks.db"));
-    let pool = match SqlitePool::connect_with(
-        SqliteConnectOptions::new()
-            .filename(dbpath)
-            .create_if_missing(false),
-    )
-    .await
-    {
-        Ok(pool) => pool,
+    let (response, with_ttl) = match pool::exchange(cacher_ip, &request.encode(), tls).await {
+        Ok((response, capabilities)) => (response, capabilities.contains(Capabilities::TTL)),
         Err(e) => {
-            error!("Database error: {}", e);
-            return None;
+            warn!(
+                "Failed to report {} to DNS Cacher {}: {}",
+                fqdn, cacher_ip, e
+            );
+            return;
         }
     };
-    if let Ok(Some(record)) =
-        sqlx::query_as::<_, sql_cols::StacksRecord>("SELECT * FROM stacks WHERE stack = ?")
-            .bind(stack)
-            .fetch_optional(&pool)
-            .await
-    {
-        let libloc = path::Path::new(&record.library);
-        pub fn parser(libloc: &path::Path, elements: &str) -> Option<gtk::Box> {
-            unsafe {
-                let lib = match libloading::Library::new(libloc) {
-                    Ok(lib) => lib,
-                    Err(_) => return None,
-                };
-                let func: libloading::Symbol<fn(elements: String) -> Option<gtk::Box>> =
-                    match lib.get("get_elements".as_bytes()) {
-                        Ok(data) => data,
-                        Err(_) => return None,
-                    };
-                func(elements.to_owned())
-            }
+    match cacher::Response::decode(&response, with_ttl) {
+        Ok(cacher::Response::Address { address, .. }) => {
+            info!("DNS Cacher {} refreshed {} to {}", cacher_ip, fqdn, address);
         }
-        return parser(libloc, elements);
-    }
-    None
-}
-
-pub fn get_stack_info(location: &PathBuf) -> Option<String> {
-    unsafe {
-        let lib = match libloading::Library::new(location) {
-            Ok(lib) => lib,
-            Err(_) => return None,
-        };
-        let func: libloading::Symbol<fn() -> Option<String>> = match lib.get("stacks".as_bytes()) {
-            Ok(data) => data,
-            Err(_) => return None,
-        };
-        func()
+        Ok(cacher::Response::Status(status)) => {
+            info!("DNS Cacher {} rechecked {}: {}", cacher_ip, fqdn, status);
+        }
+        Err(e) => warn!("DNS Cacher {} sent an invalid response: {}", cacher_ip, e),
     }
 }
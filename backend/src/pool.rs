@@ -0,0 +1,204 @@
+//! Connections to resolvers, kept open between lookups.
+//!
+//! Resolvers that agree to `KEEP_ALIVE` during the handshake answer any number
+//! of requests on one connection. When an exchange on such a connection is
+//! done it is parked here, and the next exchange with the same address picks
+//! it up instead of connecting and handshaking again. Parked connections are
+//! dropped after `IDLE_TIMEOUT`, before the resolver's own read timeout would
+//! close them.
+//!
+//! Every exchange, connecting and handshaking included, must finish within
+//! `HOP_TIMEOUT`, so one silent resolver cannot stall a lookup.
+
+use crate::health;
+use async_std::future;
+use futures_rustls::TlsConnector;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{LazyLock, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tracing::{debug, trace, warn};
+use utils::{
+    Status,
+    compression::{receive_message_async, send_message_async},
+    framing::{FrameError, FrameLimits},
+    handshake::{Capabilities, Session, client_handshake},
+    tls::{self, Stream},
+};
+
+const RESPONSE_LIMITS: FrameLimits = FrameLimits::new(64 * 1024, Some(Duration::from_secs(10)));
+/// Optional protocol features this client offers to resolvers.
+const CAPABILITIES: Capabilities = Capabilities::from_bits(
+    Capabilities::KEEP_ALIVE.bits() | Capabilities::TTL.bits() | Capabilities::ALTERNATES.bits(),
+);
+/// Longest one exchange with a resolver may take. Resolvers that look names
+/// up elsewhere on a client's behalf must answer well within it.
+pub const HOP_TIMEOUT: Duration = Duration::from_secs(5);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(8);
+const MAX_IDLE_PER_ADDRESS: usize = 4;
+
+static IDLE: LazyLock<Mutex<HashMap<String, Vec<Idle>>>> = LazyLock::new(Default::default);
+
+struct Idle {
+    connection: Connection,
+    since: Instant,
+}
+
+struct Connection {
+    address: String,
+    stream: Stream,
+    session: Session,
+}
+
+impl Connection {
+    /// Connects to `address`, over TLS with `tls`, and negotiates a protocol
+    /// version with it.
+    async fn open(address: &str, tls: Option<&TlsConnector>) -> Result<Self, Status> {
+        let mut stream = tls::connect(address, tls, &RESPONSE_LIMITS)
+            .await
+            .map_err(|e| {
+                debug!("Failed to connect to {}: {}", address, e);
+                Status::HostUnreachable
+            })?;
+        let session = match client_handshake(&mut stream, CAPABILITIES, &RESPONSE_LIMITS).await {
+            Ok(session) => session,
+            Err(e) => {
+                warn!("Handshake with {} failed: {}", address, e);
+                return Err(e.status());
+            }
+        };
+        Ok(Self {
+            address: address.to_owned(),
+            stream,
+            session,
+        })
+    }
+
+    async fn round_trip(&mut self, request: &[u8]) -> Result<Vec<u8>, ExchangeError> {
+        let compression = self.session.compression();
+        send_message_async(request, &mut self.stream, compression)
+            .await
+            .map_err(ExchangeError::Send)?;
+        receive_message_async(&mut self.stream, &RESPONSE_LIMITS, compression)
+            .await
+            .map_err(ExchangeError::Receive)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum ExchangeError {
+    Connect(Status),
+    Send(FrameError),
+    Receive(FrameError),
+    TimedOut,
+}
+
+impl ExchangeError {
+    pub(crate) fn status(&self) -> Status {
+        match self {
+            ExchangeError::Connect(status) => *status,
+            ExchangeError::Send(_) => Status::HostUnreachable,
+            ExchangeError::Receive(FrameError::TimedOut) | ExchangeError::TimedOut => {
+                Status::TimedOut
+            }
+            ExchangeError::Receive(_) => Status::BadResponse,
+        }
+    }
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExchangeError::Connect(status) => write!(f, "failed to connect: {status}"),
+            ExchangeError::Send(e) => write!(f, "failed to send request: {e}"),
+            ExchangeError::Receive(e) => write!(f, "failed to receive response: {e}"),
+            ExchangeError::TimedOut => {
+                write!(f, "no response within {}s", HOP_TIMEOUT.as_secs())
+            }
+        }
+    }
+}
+
+/// Sends `request` to the resolver at `address` and returns its response,
+/// together with the capabilities of the session it arrived on. New
+/// connections use TLS through `tls` when given. A parked connection is
+/// reused when there is one. The outcome counts towards the resolver's
+/// `health`.
+pub(crate) async fn exchange(
+    address: &str,
+    request: &[u8],
+    tls: Option<&TlsConnector>,
+) -> Result<(Vec<u8>, Capabilities), ExchangeError> {
+    let result = future::timeout(HOP_TIMEOUT, try_exchange(address, request, tls))
+        .await
+        .unwrap_or(Err(ExchangeError::TimedOut));
+    match result {
+        Ok(_) => health::record_success(address),
+        Err(_) => health::record_failure(address),
+    }
+    result
+}
+
+async fn try_exchange(
+    address: &str,
+    request: &[u8],
+    tls: Option<&TlsConnector>,
+) -> Result<(Vec<u8>, Capabilities), ExchangeError> {
+    if let Some(mut connection) = take(address, tls.is_some()) {
+        trace!("Reusing connection to {}", address);
+        match connection.round_trip(request).await {
+            Ok(response) => {
+                let capabilities = connection.session.capabilities;
+                park(connection);
+                return Ok((response, capabilities));
+            }
+            Err(e) => debug!(
+                "Parked connection to {} failed, reconnecting: {}",
+                address, e
+            ),
+        }
+    }
+    let mut connection = Connection::open(address, tls)
+        .await
+        .map_err(ExchangeError::Connect)?;
+    let response = connection.round_trip(request).await?;
+    let capabilities = connection.session.capabilities;
+    park(connection);
+    Ok((response, capabilities))
+}
+
+/// Drops every parked connection.
+pub fn close_idle_connections() {
+    IDLE.lock().unwrap_or_else(PoisonError::into_inner).clear();
+}
+
+/// A parked connection to `address`, encrypted or not as asked.
+fn take(address: &str, encrypted: bool) -> Option<Connection> {
+    let mut idle = IDLE.lock().unwrap_or_else(PoisonError::into_inner);
+    let parked = idle.get_mut(address)?;
+    parked.retain(|parked| parked.since.elapsed() < IDLE_TIMEOUT);
+    let at = parked
+        .iter()
+        .rposition(|parked| parked.connection.stream.is_encrypted() == encrypted)?;
+    Some(parked.remove(at).connection)
+}
+
+fn park(connection: Connection) {
+    if !connection
+        .session
+        .capabilities
+        .contains(Capabilities::KEEP_ALIVE)
+    {
+        return;
+    }
+    let mut idle = IDLE.lock().unwrap_or_else(PoisonError::into_inner);
+    let parked = idle.entry(connection.address.clone()).or_default();
+    if parked.len() < MAX_IDLE_PER_ADDRESS {
+        parked.push(Idle {
+            connection,
+            since: Instant::now(),
+        });
+    }
+}
@@ -0,0 +1,71 @@
+//! What `resolve` found out, and how.
+
+use std::{fmt, time::Duration};
+use utils::Status;
+
+/// Outcome of resolving one address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    /// `None` when the lookup failed.
+    pub address: Option<String>,
+    pub status: Status,
+    /// How long the answer may be reused; zero when it should not be.
+    pub ttl: Duration,
+    /// Who gave the final status, `None` if nobody did in time.
+    pub source: Option<Source>,
+    /// Every resolver asked, in order, with what it said.
+    pub hops: Vec<Hop>,
+}
+
+impl Resolution {
+    /// Whether the name itself is registered, rather than matched by a
+    /// wildcard record.
+    pub fn is_authoritative(&self) -> bool {
+        self.status == Status::Success
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    /// Answered from `cache` without asking anyone.
+    Memory,
+    Cacher,
+    Provider,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Memory => write!(f, "memory"),
+            Source::Cacher => write!(f, "cacher"),
+            Source::Provider => write!(f, "provider"),
+        }
+    }
+}
+
+/// One exchange with a resolver during a lookup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub server: String,
+    /// The name the server was asked about.
+    pub name: String,
+    pub status: Status,
+    /// The address it answered with or sent the lookup on to, if any.
+    pub address: Option<String>,
+    pub ttl: Option<u32>,
+    /// How long the exchange took.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.server, self.name, self.status)?;
+        if let Some(address) = &self.address {
+            write!(f, " -> {address}")?;
+        }
+        if let Some(ttl) = self.ttl {
+            write!(f, " ({ttl}s)")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! Which DNS providers and cachers `resolve` asks, and how.
+
+use crate::{chain::Traced, health};
+use futures::future::select_ok;
+use futures_rustls::TlsConnector;
+use std::{fmt, str::FromStr, time::Duration};
+use tracing::debug;
+use utils::Status;
+
+const DNS_IP: &str = "0.0.0.0:6202";
+const CACHER_IP: &str = "0.0.0.0:6203";
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How several endpoints of the same kind are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Strategy {
+    /// Ask one at a time, in order, moving on only when one cannot be reached.
+    #[default]
+    Failover,
+    /// Ask all at once and take the first address.
+    Parallel,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "failover" => Ok(Strategy::Failover),
+            "parallel" => Ok(Strategy::Parallel),
+            _ => Err("expected failover or parallel".to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strategy::Failover => write!(f, "failover"),
+            Strategy::Parallel => write!(f, "parallel"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ResolverConfig {
+    /// Root DNS providers, as `host:port`. May be empty.
+    pub providers: Vec<String>,
+    /// DNS cachers, as `host:port`. May be empty.
+    pub cachers: Vec<String>,
+    pub strategy: Strategy,
+    /// Wait for both the cachers and the providers and compare their answers,
+    /// reporting disagreements to the cachers.
+    pub integrity_check: bool,
+    /// Longest a whole resolution may take, across every resolver and hop.
+    pub timeout: Duration,
+    /// Connects to every resolver over TLS when set, trusting its roots.
+    pub tls: Option<TlsConnector>,
+}
+
+impl fmt::Debug for ResolverConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResolverConfig")
+            .field("providers", &self.providers)
+            .field("cachers", &self.cachers)
+            .field("strategy", &self.strategy)
+            .field("integrity_check", &self.integrity_check)
+            .field("timeout", &self.timeout)
+            .field("tls", &self.tls.is_some())
+            .finish()
+    }
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec![DNS_IP.to_owned()],
+            cachers: vec![CACHER_IP.to_owned()],
+            strategy: Strategy::default(),
+            integrity_check: false,
+            timeout: TIMEOUT,
+            tls: None,
+        }
+    }
+}
+
+/// Whether a lookup ending in `status` failed to get any answer at all, so
+/// another endpoint may do better.
+pub(crate) fn is_unreachable(status: Status) -> bool {
+    matches!(
+        status,
+        Status::HostUnreachable
+            | Status::TimedOut
+            | Status::BadResponse
+            | Status::UpgradeRequired
+            | Status::DowngradeRequired
+    )
+}
+
+/// Runs `task` against `endpoints` following `strategy`, passing over
+/// endpoints that are down while others are up. With failover the trace
+/// covers every endpoint tried; in parallel only the one that answered, or
+/// the last to fail.
+pub(crate) async fn query<F, Fut>(endpoints: &[String], strategy: Strategy, task: F) -> Traced
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Traced>,
+{
+    let endpoints = health::usable(endpoints);
+    if endpoints.is_empty() {
+        return ((None, Status::HostUnreachable, None), Vec::new());
+    }
+    match strategy {
+        Strategy::Failover => {
+            let mut lookup = (None, Status::HostUnreachable, None);
+            let mut trace = Vec::new();
+            for endpoint in endpoints {
+                let hops;
+                (lookup, hops) = task(endpoint.clone()).await;
+                trace.extend(hops);
+                if !is_unreachable(lookup.1) {
+                    break;
+                }
+                debug!("{} could not be reached: {}", endpoint, lookup.1);
+            }
+            (lookup, trace)
+        }
+        Strategy::Parallel => {
+            let task = &task;
+            let attempts = endpoints.into_iter().map(|endpoint| {
+                Box::pin(async move {
+                    let traced = task(endpoint).await;
+                    if traced.0.0.is_some() {
+                        Ok(traced)
+                    } else {
+                        Err(traced)
+                    }
+                })
+            });
+            match select_ok(attempts).await {
+                Ok((traced, _)) => traced,
+                Err(traced) => traced,
+            }
+        }
+    }
+}
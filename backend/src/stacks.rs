@@ -0,0 +1,73 @@
+//! Loading stacks, the plugins that render page content.
+//!
+//! Rendering needs GTK, so `parse_stack` is only built with the `gtk` feature.
+
+#[cfg(feature = "gtk")]
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+#[cfg(feature = "gtk")]
+use std::path;
+use std::path::PathBuf;
+#[cfg(feature = "gtk")]
+use tracing::error;
+#[cfg(feature = "gtk")]
+use utils::{get_config_dir, sql_cols};
+
+#[cfg(feature = "gtk")]
+pub async fn parse_stack(elements: &str, stack: &str, applet: &str) -> Option<gtk::Box> {
+    let config_dir = match get_config_dir(applet) {
+        Some(dir) => dir,
+        None => return None,
+    };
+    let dbpath = config_dir.join(path::Path::new("stacks.db"));
+    let pool = match SqlitePool::connect_with(
+        SqliteConnectOptions::new()
+            .filename(dbpath)
+            .create_if_missing(false),
+    )
+    .await
+    {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("Database error: {}", e);
+            return None;
+        }
+    };
+    if let Ok(Some(record)) =
+        sqlx::query_as::<_, sql_cols::StacksRecord>("SELECT * FROM stacks WHERE stack = ?")
+            .bind(stack)
+            .fetch_optional(&pool)
+            .await
+    {
+        let libloc = path::Path::new(&record.library);
+        pub fn parser(libloc: &path::Path, elements: &str) -> Option<gtk::Box> {
+            unsafe {
+                let lib = match libloading::Library::new(libloc) {
+                    Ok(lib) => lib,
+                    Err(_) => return None,
+                };
+                let func: libloading::Symbol<fn(elements: String) -> Option<gtk::Box>> =
+                    match lib.get("get_elements".as_bytes()) {
+                        Ok(data) => data,
+                        Err(_) => return None,
+                    };
+                func(elements.to_owned())
+            }
+        }
+        return parser(libloc, elements);
+    }
+    None
+}
+
+pub fn get_stack_info(location: &PathBuf) -> Option<String> {
+    unsafe {
+        let lib = match libloading::Library::new(location) {
+            Ok(lib) => lib,
+            Err(_) => return None,
+        };
+        let func: libloading::Symbol<fn() -> Option<String>> = match lib.get("stacks".as_bytes()) {
+            Ok(data) => data,
+            Err(_) => return None,
+        };
+        func()
+    }
+}
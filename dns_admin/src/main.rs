@@ -0,0 +1,230 @@
+use futures_rustls::TlsConnector;
+use std::{path, process::ExitCode, time::Duration};
+use tracing::level_filters::LevelFilter;
+use utils::{
+    Status,
+    auth::Key,
+    config::{Config, Opt, Spec},
+    framing::{FrameLimits, receive_data_async, send_data_async},
+    handshake::{Capabilities, client_handshake},
+    protocol::{
+        admin::{Command, Record, Response},
+        provider::Request,
+    },
+    tls,
+};
+
+const SPEC: Spec = Spec {
+    program: "dns_admin",
+    about: "Manages the records of a DNS provider. COMMAND is one of:\n  \
+            list          show every record the key may manage\n  \
+            get NAME      show one record\n  \
+            set NAME      create or replace a record from --domain, --dns and --ttl\n  \
+            delete NAME   remove a record\n\
+            NAME is one block, e.g. `example`, or `.` for the move and wildcard record.",
+    applet: "dns_admin",
+    env_prefix: "DNS_ADMIN",
+    options: &[
+        Opt::value("server", Some('s'), "ADDRESS", "DNS provider to manage")
+            .default("0.0.0.0:6202"),
+        Opt::value(
+            "key-id",
+            Some('k'),
+            "ID",
+            "Id of the pre-shared key the provider knows",
+        ),
+        Opt::value(
+            "key",
+            None,
+            "HEX",
+            "Secret of the pre-shared key; prefer $DNS_ADMIN_KEY or the config file",
+        ),
+        Opt::value(
+            "domain",
+            None,
+            "ADDRESS",
+            "For set: ip:port of the site itself",
+        ),
+        Opt::list(
+            "dns",
+            None,
+            "ADDRESS",
+            "For set: ip:port of the DNS provider to delegate to; repeat for secondaries, tried in order",
+        ),
+        Opt::value(
+            "ttl",
+            None,
+            "SECONDS",
+            "For set: how long answers may be cached",
+        )
+        .default("300"),
+        Opt::value(
+            "tls-ca",
+            None,
+            "FILE",
+            "PEM certificates to trust; enables TLS",
+        ),
+        Opt::operands("command", "COMMAND", "What to do, see above"),
+        Opt::count("verbose", Some('v'), "Log to stderr; repeat for more"),
+    ],
+};
+const RESPONSE_LIMITS: FrameLimits =
+    FrameLimits::new(16 * 1024 * 1024, Some(Duration::from_secs(10)));
+
+#[async_std::main]
+async fn main() -> ExitCode {
+    let config = Config::load_or_exit(&SPEC);
+    let log_level = match config.count("verbose") {
+        0 => LevelFilter::OFF,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(log_level)
+        .with_writer(std::io::stderr)
+        .init();
+    let command = match command(&config) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("dns_admin: {e}");
+            eprintln!("Try 'dns_admin --help' for more information.");
+            return ExitCode::from(2);
+        }
+    };
+    let key = match (config.get("key-id"), config.get("key")) {
+        (Some(id), Some(secret)) => match Key::from_hex(id, secret, None) {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("dns_admin: bad --key: {e}");
+                return ExitCode::from(2);
+            }
+        },
+        _ => {
+            eprintln!("dns_admin: --key-id and --key are required");
+            return ExitCode::from(2);
+        }
+    };
+    let tls = match config
+        .get("tls-ca")
+        .map(path::Path::new)
+        .map(tls::client_connector)
+    {
+        None => None,
+        Some(Ok(connector)) => Some(connector),
+        Some(Err(e)) => {
+            eprintln!("dns_admin: failed to set up TLS: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let signed = match key.sign(command.clone()) {
+        Ok(signed) => signed,
+        Err(e) => {
+            eprintln!("dns_admin: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let server = config.get("server").unwrap_or_default();
+    match exchange(server, tls.as_ref(), &Request::Manage(signed)).await {
+        Ok(response) => report(&command, response),
+        Err(e) => {
+            eprintln!("dns_admin: {server}: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn command(config: &Config) -> Result<Command, String> {
+    let operands = config.list("command");
+    let name = || match operands.as_slice() {
+        [_, name] => Ok((*name).to_owned()),
+        [command] => Err(format!("{command} needs a NAME")),
+        _ => Err("expected one NAME".to_owned()),
+    };
+    match operands.first().copied() {
+        None => Err("no command given".to_owned()),
+        Some("list") if operands.len() == 1 => Ok(Command::List),
+        Some("list") => Err("list takes no NAME".to_owned()),
+        Some("get") => Ok(Command::Get { name: name()? }),
+        Some("delete") => Ok(Command::Delete { name: name()? }),
+        Some("set") => {
+            let record = Record {
+                name: name()?,
+                domain: config
+                    .get("domain")
+                    .map(|address| parse_address("domain", address))
+                    .transpose()?,
+                dns: config
+                    .list("dns")
+                    .into_iter()
+                    .map(|address| parse_address("dns", address))
+                    .collect::<Result<_, _>>()?,
+                ttl: config.require("ttl").map_err(|e| e.to_string())?,
+            };
+            if record.domain.is_none() && record.dns.is_empty() {
+                return Err("set needs --domain, --dns or both".to_owned());
+            }
+            Ok(Command::Put(record))
+        }
+        Some(other) => Err(format!("unknown command {other:?}")),
+    }
+}
+
+fn parse_address(long: &str, address: &str) -> Result<(String, u16), String> {
+    address
+        .rsplit_once(':')
+        .and_then(|(ip, port)| Some((ip.to_owned(), port.parse().ok()?)))
+        .ok_or_else(|| format!("--{long} expects ip:port, got {address:?}"))
+}
+
+/// Sends `request` to the provider at `server`, over TLS when `tls` is set,
+/// and reads its answer.
+async fn exchange(
+    server: &str,
+    tls: Option<&TlsConnector>,
+    request: &Request,
+) -> Result<Response, String> {
+    let request = request.encode().map_err(|e| e.to_string())?;
+    let mut stream = tls::connect(server, tls, &RESPONSE_LIMITS)
+        .await
+        .map_err(|e| format!("failed to connect: {e}"))?;
+    client_handshake(&mut stream, Capabilities::NONE, &RESPONSE_LIMITS)
+        .await
+        .map_err(|e| e.to_string())?;
+    send_data_async(&request, &mut stream)
+        .await
+        .map_err(|e| format!("failed to send request: {e}"))?;
+    let data = receive_data_async(&mut stream, &RESPONSE_LIMITS)
+        .await
+        .map_err(|e| format!("failed to receive response: {e}"))?;
+    Response::decode(&data).map_err(|e| e.to_string())
+}
+
+fn report(command: &Command, response: Response) -> ExitCode {
+    match (command, response) {
+        (_, Response::Records(records)) => {
+            for record in records {
+                println!("{record}");
+            }
+        }
+        (_, Response::Transfer(transfer)) => {
+            for record in transfer.records {
+                println!("{record}");
+            }
+        }
+        (Command::Put(record), Response::Status(Status::Created)) => {
+            println!("Created {}.", record.name)
+        }
+        (Command::Put(record), Response::Status(Status::Success)) => {
+            println!("Updated {}.", record.name)
+        }
+        (Command::Delete { name }, Response::Status(Status::Success)) => {
+            println!("Deleted {name}.")
+        }
+        (_, Response::Status(status)) => {
+            eprintln!("dns_admin: {status}");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
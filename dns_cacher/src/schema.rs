@@ -0,0 +1,280 @@
+//! Checking and creating the `dns_cache` table, in either SQL dialect.
+
+use tracing::{error, info, trace, warn};
+use utils::{
+    sql_cols,
+    storage::{Dialect, Storage},
+    with_pool,
+};
+
+/// Columns in an up-to-date `dns_cache` table.
+const COLUMNS: i32 = 6;
+
+struct Schema {
+    /// Counts the columns that match what `resolve` expects; `COLUMNS` when
+    /// all do.
+    check: &'static str,
+    create: &'static str,
+    /// Columns added since the first release, oldest first, for upgrading
+    /// tables created without them.
+    upgrades: [Upgrade; 2],
+}
+
+struct Upgrade {
+    column: &'static str,
+    /// Counts the columns named `column`.
+    has: &'static str,
+    add: &'static str,
+}
+
+const MYSQL: Schema = Schema {
+    check: r#"
+        SELECT
+            COUNT(*) as count
+        FROM
+            INFORMATION_SCHEMA.COLUMNS
+        WHERE
+            TABLE_NAME = 'dns_cache'
+        AND (
+            (COLUMN_NAME = 'id' AND DATA_TYPE = 'int' AND IS_NULLABLE = 'NO')
+            OR (COLUMN_NAME = 'name' AND DATA_TYPE = 'varchar' AND CHARACTER_MAXIMUM_LENGTH = 255 AND IS_NULLABLE = 'NO')
+            OR (COLUMN_NAME = 'domain_ip' AND DATA_TYPE = 'varchar' AND CHARACTER_MAXIMUM_LENGTH = 63 AND IS_NULLABLE = 'YES')
+            OR (COLUMN_NAME = 'domain_port' AND DATA_TYPE = 'smallint' AND IS_NULLABLE = 'YES')
+            OR (COLUMN_NAME = 'ttl' AND DATA_TYPE = 'int' AND IS_NULLABLE = 'NO')
+            OR (COLUMN_NAME = 'cached_at' AND DATA_TYPE = 'bigint' AND IS_NULLABLE = 'YES')
+        );
+        "#,
+    create: r#"
+        CREATE TABLE IF NOT EXISTS dns_cache (
+            id INT AUTO_INCREMENT PRIMARY KEY,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            domain_ip VARCHAR(63) NULL,
+            domain_port SMALLINT UNSIGNED NULL CHECK (domain_port BETWEEN 0 AND 25565),
+            ttl INT UNSIGNED NOT NULL DEFAULT 300,
+            cached_at BIGINT NULL
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+        "#,
+    upgrades: [
+        Upgrade {
+            column: "ttl",
+            has: r#"
+            SELECT
+                COUNT(*) as count
+            FROM
+                INFORMATION_SCHEMA.COLUMNS
+            WHERE
+                TABLE_NAME = 'dns_cache'
+            AND COLUMN_NAME = 'ttl';
+            "#,
+            add: "ALTER TABLE dns_cache ADD COLUMN ttl INT UNSIGNED NOT NULL DEFAULT 300;",
+        },
+        Upgrade {
+            column: "cached_at",
+            has: r#"
+            SELECT
+                COUNT(*) as count
+            FROM
+                INFORMATION_SCHEMA.COLUMNS
+            WHERE
+                TABLE_NAME = 'dns_cache'
+            AND COLUMN_NAME = 'cached_at';
+            "#,
+            add: "ALTER TABLE dns_cache ADD COLUMN cached_at BIGINT NULL;",
+        },
+    ],
+};
+
+const SQLITE: Schema = Schema {
+    check: r#"
+        SELECT
+            COUNT(*) as count
+        FROM
+            pragma_table_info('dns_cache')
+        WHERE
+            (name = 'id' AND type = 'INTEGER' AND pk = 1)
+            OR (name = 'name' AND type = 'VARCHAR(255)' AND "notnull" = 1)
+            OR (name = 'domain_ip' AND type = 'VARCHAR(63)' AND "notnull" = 0)
+            OR (name = 'domain_port' AND type = 'INTEGER' AND "notnull" = 0)
+            OR (name = 'ttl' AND type = 'INTEGER' AND "notnull" = 1)
+            OR (name = 'cached_at' AND type = 'INTEGER' AND "notnull" = 0);
+        "#,
+    create: r#"
+        CREATE TABLE IF NOT EXISTS dns_cache (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            domain_ip VARCHAR(63) NULL,
+            domain_port INTEGER NULL CHECK (domain_port BETWEEN 0 AND 25565),
+            ttl INTEGER NOT NULL DEFAULT 300 CHECK (ttl >= 0),
+            cached_at INTEGER NULL
+        );
+        "#,
+    upgrades: [
+        Upgrade {
+            column: "ttl",
+            has: r#"
+            SELECT
+                COUNT(*) as count
+            FROM
+                pragma_table_info('dns_cache')
+            WHERE
+                name = 'ttl';
+            "#,
+            add: "ALTER TABLE dns_cache ADD COLUMN ttl INTEGER NOT NULL DEFAULT 300 CHECK (ttl >= 0);",
+        },
+        Upgrade {
+            column: "cached_at",
+            has: r#"
+            SELECT
+                COUNT(*) as count
+            FROM
+                pragma_table_info('dns_cache')
+            WHERE
+                name = 'cached_at';
+            "#,
+            add: "ALTER TABLE dns_cache ADD COLUMN cached_at INTEGER NULL;",
+        },
+    ],
+};
+
+fn schema(storage: &Storage) -> &'static Schema {
+    match storage.dialect() {
+        Dialect::MySql => &MYSQL,
+        Dialect::Sqlite => &SQLITE,
+    }
+}
+
+pub(crate) async fn check_database(storage: &Storage, overwrite: bool) {
+    trace!("Checking database schema integrity...");
+    add_missing_columns(storage).await;
+    let check = with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Count>(schema(storage).check)
+            .fetch_optional(pool)
+            .await
+    });
+    match check {
+        Ok(Some(e)) => {
+            if e.count == COLUMNS {
+                trace!("Database schema integrity check passed.");
+            } else if overwrite {
+                warn!("Database schema mismatch. Will overwite.");
+                overwrite_database(storage).await;
+                trace!("Database schema overwritten.");
+            } else {
+                error!("Invalid database! Run with --overwrite to overwrite existing database.");
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            if overwrite {
+                warn!("Failure to locate database. Will overwite.");
+                overwrite_database(storage).await;
+                trace!("Database overwritten.");
+            } else {
+                error!(
+                    "Failed to locate databse! Run with --overwrite to generate a new database."
+                );
+                std::process::exit(1);
+            }
+        }
+    };
+}
+
+async fn overwrite_database(storage: &Storage) {
+    let schema = schema(storage);
+    match with_pool!(storage, |pool| {
+        sqlx::query(schema.create).execute(pool).await.map(|_| ())
+    }) {
+        Ok(_) => {
+            info!("{} database successfully created.", storage.dialect());
+        }
+        Err(e) => {
+            error!("Failed to create database: {}", e);
+            std::process::exit(1);
+        }
+    };
+}
+
+/// Counts what `query` selects, or `None` if it fails.
+async fn count(storage: &Storage, query: &str) -> Option<i32> {
+    with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Count>(query)
+            .fetch_optional(pool)
+            .await
+    })
+    .ok()
+    .flatten()
+    .map(|e| e.count)
+}
+
+/// Upgrades a table created before entries carried a TTL or the time they
+/// were filled in place, so that only real mismatches need `--overwrite`.
+/// Entries from before `cached_at` count as entered by hand.
+async fn add_missing_columns(storage: &Storage) {
+    let schema = schema(storage);
+    let upgrades = schema.upgrades.len() as i32;
+    for (index, upgrade) in schema.upgrades.iter().enumerate() {
+        // A table from before this column has every earlier one once the
+        // upgrades before it ran, and none of the later ones.
+        if count(storage, upgrade.has).await != Some(0)
+            || count(storage, schema.check).await != Some(COLUMNS - upgrades + index as i32)
+        {
+            continue;
+        }
+        match with_pool!(storage, |pool| {
+            sqlx::query(upgrade.add).execute(pool).await.map(|_| ())
+        }) {
+            Ok(_) => info!("Added {} column to dns_cache.", upgrade.column),
+            Err(e) => {
+                error!("Failed to add {} column: {}", upgrade.column, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::storage::PoolSettings;
+
+    #[async_std::test]
+    async fn upgrades_tables_without_ttl_or_cached_at() {
+        let settings = PoolSettings {
+            max_connections: 1,
+            ..PoolSettings::default()
+        };
+        let storage = Storage::connect("sqlite::memory:", &settings)
+            .await
+            .unwrap();
+        let Storage::Sqlite(pool) = &storage else {
+            unreachable!()
+        };
+        sqlx::query(
+            r#"
+            CREATE TABLE dns_cache (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(255) UNIQUE NOT NULL,
+                domain_ip VARCHAR(63) NULL,
+                domain_port INTEGER NULL CHECK (domain_port BETWEEN 0 AND 25565)
+            );
+            "#,
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO dns_cache (name, domain_ip, domain_port) VALUES ('a', '10.0.0.1', 80);",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        check_database(&storage, false).await;
+        assert_eq!(count(&storage, SQLITE.check).await, Some(COLUMNS));
+        let row: (i64, Option<i64>) =
+            sqlx::query_as("SELECT ttl, cached_at FROM dns_cache WHERE name = 'a';")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert_eq!(row, (300, None));
+    }
+}
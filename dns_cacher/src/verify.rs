@@ -0,0 +1,36 @@
+//! Checking a name against the DNS provider, the way a client resolves it.
+
+use futures_rustls::TlsConnector;
+use utils::Status;
+
+/// The DNS provider whose chain names are checked against.
+#[derive(Clone)]
+pub(crate) struct Provider {
+    /// Root DNS provider, as `host:port`.
+    pub(crate) address: String,
+    /// Set when the provider chain is reached over TLS.
+    pub(crate) tls: Option<TlsConnector>,
+}
+
+/// Outcome of checking a name with the DNS provider.
+pub(crate) enum Verdict {
+    /// The name resolves to `address`, valid for `ttl` seconds if known.
+    Address { address: String, ttl: Option<u32> },
+    /// The provider says the name does not exist.
+    Missing(Status),
+    /// The provider could not be asked, so nothing is known.
+    Unknown(Status),
+}
+
+/// Resolves `fqdn` through the provider chain rooted at `provider`.
+pub(crate) async fn verify(fqdn: &str, provider: &Provider) -> Verdict {
+    match backend::dns_task(&provider.address, fqdn, provider.tls.as_ref()).await {
+        (Some(address), Status::Success | Status::Gone, ttl) => Verdict::Address { address, ttl },
+        // A wildcard answer says the name itself is not registered.
+        (Some(_), status, _) => Verdict::Missing(status),
+        (None, status @ (Status::NotFound | Status::Gone | Status::Misdirected), _) => {
+            Verdict::Missing(status)
+        }
+        (None, status, _) => Verdict::Unknown(status),
+    }
+}
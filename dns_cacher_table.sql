@@ -7,9 +7,16 @@
 -- To avoid performance issues, the cacher can only cache FQDNs shorter than or equal to
 -- 255 characters.
 
+-- ttl is how many seconds clients may keep an answer before asking again.
+-- cached_at is the Unix time dns_cacher --fill stored the answer; once ttl seconds
+-- have passed it asks the DNS provider again. Leave it NULL for entries entered
+-- by hand, which never expire.
+
 CREATE TABLE dns_cache (
   id INT AUTO_INCREMENT PRIMARY KEY,
   name VARCHAR(255) UNIQUE NOT NULL,
   domain_ip VARCHAR(63) NULL,
   domain_port SMALLINT UNSIGNED NULL CHECK (domain_port BETWEEN 0 AND 25565),
+  ttl INT UNSIGNED NOT NULL DEFAULT 300,
+  cached_at BIGINT NULL
 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
\ No newline at end of file
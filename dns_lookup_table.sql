@@ -16,11 +16,25 @@
 -- is especially useful if you are say hosting the DNS Server for .com, and you want that if a
 -- client requests a nonexistant .com domain to be taken to your 'purchase this domain' page.
 
+-- ttl is how many seconds clients may keep an answer before asking again. A name
+-- resolved through several DNS Servers lasts as long as the shortest ttl on the way.
+
+-- To keep records in version control, write them as a zone file (the format is
+-- described in dns_provider/src/zone.rs) and load it with
+-- dns_provider --import-zone FILE. --export-zone FILE writes the table out again.
+
+-- dns_provider also keeps dns_delegations, for names delegated to more than one
+-- DNS Server, and dns_journal, so that secondaries started with --primary can
+-- copy only what changed. Secondaries note the last serial they copied in
+-- dns_transfer_state. It creates all three itself; use dns_admin or a zone file
+-- rather than editing them by hand.
+
 CREATE TABLE dns_records (
   id INT AUTO_INCREMENT PRIMARY KEY,
   name VARCHAR(255) UNIQUE NOT NULL,
   domain_ip VARCHAR(63) NULL,
   domain_port SMALLINT UNSIGNED NULL CHECK (domain_port BETWEEN 0 AND 25565),
   dns_ip VARCHAR(63) NULL,
-  dns_port SMALLINT UNSIGNED NULL CHECK (dns_port BETWEEN 0 AND 25565)
+  dns_port SMALLINT UNSIGNED NULL CHECK (dns_port BETWEEN 0 AND 25565),
+  ttl INT UNSIGNED NOT NULL DEFAULT 300
 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
\ No newline at end of file
@@ -0,0 +1,113 @@
+//! Changing `dns_records` on behalf of authenticated clients.
+
+use crate::{
+    records::{delete, get, list, put, validate},
+    transfer::changes,
+};
+use tracing::{info, warn};
+use utils::{
+    Status,
+    auth::KeyRing,
+    protocol::admin::{Command, Response, Signed},
+    storage::Storage,
+};
+
+/// Answers `signed` if a key in `keys` made it. A `read_only` provider, one
+/// following a primary, refuses to change records itself.
+pub(crate) async fn manage(
+    signed: &Signed,
+    keys: Option<&KeyRing>,
+    storage: &Storage,
+    read_only: bool,
+) -> Response {
+    let Some(keys) = keys else {
+        warn!("Refusing management request, no --admin-keys configured.");
+        return Response::Status(Status::Forbidden);
+    };
+    let key = match keys.authenticate(signed) {
+        Ok(key) => key,
+        Err(status) => {
+            warn!(
+                "Rejected management request claiming key {:?}.",
+                signed.key_id
+            );
+            return Response::Status(status);
+        }
+    };
+    if let Some(name) = signed.command.name()
+        && !key.may_manage(name)
+    {
+        warn!("Key {} may not manage {}.", key.id, name);
+        return Response::Status(Status::Forbidden);
+    }
+    if read_only && matches!(signed.command, Command::Put(_) | Command::Delete { .. }) {
+        warn!(
+            "Key {} tried to change records on a secondary, refusing.",
+            key.id
+        );
+        return Response::Status(Status::Forbidden);
+    }
+    match &signed.command {
+        Command::List => match list(storage).await {
+            Ok(records) => Response::Records(
+                records
+                    .into_iter()
+                    .filter(|record| key.may_manage(&record.name))
+                    .collect(),
+            ),
+            Err(status) => Response::Status(status),
+        },
+        Command::Get { name } => match get(storage, name).await {
+            Ok(Some(record)) => Response::Records(vec![record]),
+            Ok(None) => Response::Status(Status::NotFound),
+            Err(status) => Response::Status(status),
+        },
+        Command::Put(record) => {
+            if let Err(reason) = validate(record) {
+                warn!("Key {} sent an invalid record: {}", key.id, reason);
+                return Response::Status(Status::Unprocessable);
+            }
+            let existed = match get(storage, &record.name).await {
+                Ok(existing) => existing.is_some(),
+                Err(status) => return Response::Status(status),
+            };
+            if let Err(status) = put(storage, record).await {
+                return Response::Status(status);
+            }
+            info!("Key {} set {}.", key.id, record);
+            Response::Status(if existed {
+                Status::Success
+            } else {
+                Status::Created
+            })
+        }
+        Command::Delete { name } => match delete(storage, name).await {
+            Ok(false) => Response::Status(Status::NotFound),
+            Ok(true) => {
+                info!("Key {} deleted {}.", key.id, name);
+                Response::Status(Status::Success)
+            }
+            Err(status) => Response::Status(status),
+        },
+        Command::Transfer { since } => {
+            // A transfer reveals every record, so only keys that could list
+            // them all may ask for one.
+            if !key.is_unrestricted() {
+                warn!("Key {} may not transfer the zone.", key.id);
+                return Response::Status(Status::Forbidden);
+            }
+            match changes(storage, *since).await {
+                Ok(transfer) => {
+                    info!(
+                        "Key {} transferred {} records up to serial {}.",
+                        key.id,
+                        transfer.records.len(),
+                        transfer.serial
+                    );
+                    Response::Transfer(transfer)
+                }
+                Err(status) => Response::Status(status),
+            }
+        }
+    }
+}
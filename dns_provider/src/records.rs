@@ -0,0 +1,538 @@
+//! Reading and writing whole records.
+//!
+//! A record is its row of `dns_records`, which holds the first DNS provider it
+//! is delegated to, plus any further ones in `dns_delegations`. Every change is
+//! noted in `dns_journal` in the same transaction, so secondaries can ask for
+//! only what changed since they last looked. A secondary in turn keeps the
+//! serial it last applied in `dns_transfer_state`.
+
+use std::collections::HashMap;
+use tracing::error;
+use utils::{
+    Status,
+    protocol::admin::{Record, Transfer},
+    sql_cols,
+    storage::{Dialect, Storage},
+    with_pool,
+};
+
+/// Highest port the `dns_records` schema accepts.
+const MAX_PORT: u16 = 25565;
+/// Most DNS providers one name may be delegated to.
+const MAX_DELEGATES: usize = 16;
+/// Journal entry for replacing every record at once, after which secondaries
+/// have to transfer the whole zone again.
+const RESET: &str = "";
+
+/// Notes in `dns_journal` that `$name` changed, within `$transaction`.
+macro_rules! journal {
+    ($transaction:expr, $name:expr) => {
+        sqlx::query("INSERT INTO dns_journal (name) VALUES (?);")
+            .bind($name)
+            .execute(&mut *$transaction)
+            .await?;
+    };
+}
+
+/// Inserts or replaces `$record` and its delegations within `$transaction`.
+/// A macro so that `with_pool!` can expand it once per database.
+macro_rules! write_record {
+    ($transaction:expr, $upsert:expr, $record:expr) => {{
+        let record: &Record = $record;
+        let (domain_ip, domain_port) = record.domain.clone().unzip();
+        let (dns_ip, dns_port) = record.dns.first().cloned().unzip();
+        sqlx::query($upsert)
+            .bind(&record.name)
+            .bind(domain_ip)
+            .bind(domain_port)
+            .bind(dns_ip)
+            .bind(dns_port)
+            .bind(record.ttl)
+            .execute(&mut *$transaction)
+            .await?;
+        sqlx::query("DELETE FROM dns_delegations WHERE name = ?;")
+            .bind(&record.name)
+            .execute(&mut *$transaction)
+            .await?;
+        for (position, (ip, port)) in record.dns.iter().enumerate().skip(1) {
+            sqlx::query(
+                r#"
+                INSERT INTO dns_delegations (name, position, dns_ip, dns_port)
+                VALUES (?, ?, ?, ?);
+                "#,
+            )
+            .bind(&record.name)
+            .bind(position as u16)
+            .bind(ip)
+            .bind(port)
+            .execute(&mut *$transaction)
+            .await?;
+        }
+    }};
+}
+
+/// Removes `$name` and its delegations within `$transaction`. Evaluates to
+/// the number of records removed.
+macro_rules! delete_record {
+    ($transaction:expr, $name:expr) => {{
+        let deleted = sqlx::query("DELETE FROM dns_records WHERE name = ?;")
+            .bind($name)
+            .execute(&mut *$transaction)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM dns_delegations WHERE name = ?;")
+            .bind($name)
+            .execute(&mut *$transaction)
+            .await?;
+        deleted
+    }};
+}
+
+/// Replaces every record with `$records` within `$transaction`.
+macro_rules! replace_records {
+    ($transaction:expr, $upsert:expr, $records:expr) => {
+        sqlx::query("DELETE FROM dns_records;")
+            .execute(&mut *$transaction)
+            .await?;
+        sqlx::query("DELETE FROM dns_delegations;")
+            .execute(&mut *$transaction)
+            .await?;
+        for record in $records {
+            write_record!($transaction, $upsert, record);
+        }
+        // Nothing from before can be replayed on top of the new records.
+        sqlx::query("DELETE FROM dns_journal;")
+            .execute(&mut *$transaction)
+            .await?;
+        journal!($transaction, RESET);
+    };
+}
+
+/// Drops the serial last transferred from a primary within `$transaction`,
+/// since records changed here no longer match it.
+macro_rules! forget_transfer {
+    ($transaction:expr) => {
+        sqlx::query("DELETE FROM dns_transfer_state;")
+            .execute(&mut *$transaction)
+            .await?;
+    };
+}
+
+fn upsert(dialect: Dialect) -> &'static str {
+    match dialect {
+        Dialect::MySql => {
+            r#"
+            INSERT INTO dns_records (name, domain_ip, domain_port, dns_ip, dns_port, ttl)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+                domain_ip = VALUES(domain_ip),
+                domain_port = VALUES(domain_port),
+                dns_ip = VALUES(dns_ip),
+                dns_port = VALUES(dns_port),
+                ttl = VALUES(ttl);
+            "#
+        }
+        Dialect::Sqlite => {
+            r#"
+            INSERT INTO dns_records (name, domain_ip, domain_port, dns_ip, dns_port, ttl)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (name) DO UPDATE SET
+                domain_ip = excluded.domain_ip,
+                domain_port = excluded.domain_port,
+                dns_ip = excluded.dns_ip,
+                dns_port = excluded.dns_port,
+                ttl = excluded.ttl;
+            "#
+        }
+    }
+}
+
+fn to_record(row: sql_cols::NamedRecord, mut delegates: Vec<(String, u16)>) -> Record {
+    if let Some(first) = row.dns_ip.zip(row.dns_port) {
+        delegates.insert(0, first);
+    }
+    Record {
+        name: row.name,
+        domain: row.domain_ip.zip(row.domain_port),
+        dns: delegates,
+        ttl: row.ttl,
+    }
+}
+
+pub(crate) async fn list(storage: &Storage) -> Result<Vec<Record>, Status> {
+    let rows = with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::NamedRecord>(
+            r#"
+            SELECT name, domain_ip, domain_port, dns_ip, dns_port, ttl
+            FROM dns_records
+            ORDER BY name
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+    })
+    .map_err(|e| {
+        error!("Failed to list records: {}", e);
+        Status::Misdirected
+    })?;
+    let delegations = with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Delegation>(
+            r#"
+            SELECT name, dns_ip, dns_port
+            FROM dns_delegations
+            ORDER BY name, position
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+    })
+    .map_err(|e| {
+        error!("Failed to list delegations: {}", e);
+        Status::Misdirected
+    })?;
+    let mut delegates: HashMap<String, Vec<(String, u16)>> = HashMap::new();
+    for delegation in delegations {
+        delegates
+            .entry(delegation.name)
+            .or_default()
+            .push((delegation.dns_ip, delegation.dns_port));
+    }
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let extra = delegates.remove(&row.name).unwrap_or_default();
+            to_record(row, extra)
+        })
+        .collect())
+}
+
+pub(crate) async fn get(storage: &Storage, name: &str) -> Result<Option<Record>, Status> {
+    let row = with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::NamedRecord>(
+            r#"
+            SELECT name, domain_ip, domain_port, dns_ip, dns_port, ttl
+            FROM dns_records
+            WHERE name = ?
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+    })
+    .map_err(|e| {
+        error!("Failed to fetch record for {}: {}", name, e);
+        Status::Misdirected
+    })?;
+    match row {
+        Some(row) => Ok(Some(to_record(row, delegates(storage, name).await?))),
+        None => Ok(None),
+    }
+}
+
+/// The DNS providers `name` is delegated to after the one in `dns_records`.
+pub(crate) async fn delegates(storage: &Storage, name: &str) -> Result<Vec<(String, u16)>, Status> {
+    with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Delegation>(
+            r#"
+            SELECT name, dns_ip, dns_port
+            FROM dns_delegations
+            WHERE name = ?
+            ORDER BY position
+            "#,
+        )
+        .bind(name)
+        .fetch_all(pool)
+        .await
+    })
+    .map(|rows| {
+        rows.into_iter()
+            .map(|row| (row.dns_ip, row.dns_port))
+            .collect()
+    })
+    .map_err(|e| {
+        error!("Failed to fetch delegations for {}: {}", name, e);
+        Status::Misdirected
+    })
+}
+
+/// Checks `record` against the limits of the `dns_records` schema.
+pub(crate) fn validate(record: &Record) -> Result<(), String> {
+    if record.name.is_empty() || record.name.len() > 255 {
+        return Err(format!("name is {} bytes long", record.name.len()));
+    }
+    // Clients ask about one block at a time, so no other name would be found.
+    if record.name != "."
+        && record
+            .name
+            .contains(|c: char| c == '.' || c.is_whitespace() || c.is_control())
+    {
+        return Err(format!("name {:?} is not a single block", record.name));
+    }
+    if record.domain.is_none() && record.dns.is_empty() {
+        return Err(format!(
+            "{} has neither a domain nor a DNS address",
+            record.name
+        ));
+    }
+    if record.dns.len() > MAX_DELEGATES {
+        return Err(format!(
+            "{} has {} DNS addresses, at most {MAX_DELEGATES} are allowed",
+            record.name,
+            record.dns.len()
+        ));
+    }
+    for (ip, port) in record.domain.iter().chain(&record.dns) {
+        if ip.is_empty() || ip.len() > 63 {
+            return Err(format!("address {ip:?} is {} bytes long", ip.len()));
+        }
+        // Answers list the DNS addresses separated by commas.
+        if ip.contains(',') {
+            return Err(format!("address {ip:?} contains a comma"));
+        }
+        if *port > MAX_PORT {
+            return Err(format!("port {port} is above {MAX_PORT}"));
+        }
+    }
+    Ok(())
+}
+
+/// Inserts `record`, or replaces the one with the same name.
+pub(crate) async fn put(storage: &Storage, record: &Record) -> Result<(), Status> {
+    let upsert = upsert(storage.dialect());
+    with_pool!(storage, |pool| {
+        async {
+            let mut transaction = pool.begin().await?;
+            write_record!(transaction, upsert, record);
+            journal!(transaction, &record.name);
+            forget_transfer!(transaction);
+            transaction.commit().await
+        }
+        .await
+    })
+    .map_err(|e: sqlx::Error| {
+        error!("Failed to store record for {}: {}", record.name, e);
+        Status::Misdirected
+    })
+}
+
+/// Removes the record for `name`. Returns whether there was one.
+pub(crate) async fn delete(storage: &Storage, name: &str) -> Result<bool, Status> {
+    with_pool!(storage, |pool| {
+        async {
+            let mut transaction = pool.begin().await?;
+            let deleted = delete_record!(transaction, name);
+            journal!(transaction, name);
+            forget_transfer!(transaction);
+            transaction.commit().await?;
+            Ok(deleted > 0)
+        }
+        .await
+    })
+    .map_err(|e: sqlx::Error| {
+        error!("Failed to delete record for {}: {}", name, e);
+        Status::Misdirected
+    })
+}
+
+/// Replaces every record with `records`, in one transaction.
+pub(crate) async fn replace_all(storage: &Storage, records: &[Record]) -> Result<(), Status> {
+    let upsert = upsert(storage.dialect());
+    with_pool!(storage, |pool| {
+        async {
+            let mut transaction = pool.begin().await?;
+            replace_records!(transaction, upsert, records);
+            forget_transfer!(transaction);
+            transaction.commit().await
+        }
+        .await
+    })
+    .map_err(|e: sqlx::Error| {
+        error!("Failed to replace every record: {}", e);
+        Status::Misdirected
+    })
+}
+
+/// Applies `transfer` from the provider at `primary` and notes its serial, in
+/// one transaction, so that a restart carries on from there.
+pub(crate) async fn apply_transfer(
+    storage: &Storage,
+    primary: &str,
+    transfer: &Transfer,
+) -> Result<(), Status> {
+    let upsert = upsert(storage.dialect());
+    with_pool!(storage, |pool| {
+        async {
+            let mut transaction = pool.begin().await?;
+            if transfer.full {
+                replace_records!(transaction, upsert, &transfer.records);
+            } else {
+                for record in &transfer.records {
+                    write_record!(transaction, upsert, record);
+                    journal!(transaction, &record.name);
+                }
+                for name in &transfer.deleted {
+                    delete_record!(transaction, name);
+                    journal!(transaction, name);
+                }
+            }
+            forget_transfer!(transaction);
+            sqlx::query("INSERT INTO dns_transfer_state (id, source, serial) VALUES (1, ?, ?);")
+                .bind(primary)
+                .bind(transfer.serial as i64)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await
+        }
+        .await
+    })
+    .map_err(|e: sqlx::Error| {
+        error!("Failed to apply transfer from {}: {}", primary, e);
+        Status::Misdirected
+    })
+}
+
+/// The serial last transferred from the provider at `primary`, 0 if there is
+/// none to carry on from.
+pub(crate) async fn transferred_serial(storage: &Storage, primary: &str) -> Result<u64, Status> {
+    with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Serial>(
+            "SELECT COALESCE(MAX(serial), 0) AS serial FROM dns_transfer_state WHERE source = ?",
+        )
+        .bind(primary)
+        .fetch_one(pool)
+        .await
+    })
+    .map(|row| row.serial.max(0) as u64)
+    .map_err(|e| {
+        error!("Failed to read the transferred serial: {}", e);
+        Status::Misdirected
+    })
+}
+
+/// The serial of the latest change, 0 if nothing has changed yet.
+pub(crate) async fn serial(storage: &Storage) -> Result<u64, Status> {
+    with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Serial>(
+            "SELECT COALESCE(MAX(serial), 0) AS serial FROM dns_journal",
+        )
+        .fetch_one(pool)
+        .await
+    })
+    .map(|row| row.serial.max(0) as u64)
+    .map_err(|e| {
+        error!("Failed to read the journal serial: {}", e);
+        Status::Misdirected
+    })
+}
+
+/// The names changed after serial `since`, in the order they first changed,
+/// or `None` if every record was replaced since then.
+pub(crate) async fn changed_since(
+    storage: &Storage,
+    since: u64,
+) -> Result<Option<Vec<String>>, Status> {
+    let entries = with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::JournalEntry>(
+            r#"
+            SELECT name
+            FROM dns_journal
+            WHERE serial > ?
+            ORDER BY serial
+            "#,
+        )
+        .bind(since as i64)
+        .fetch_all(pool)
+        .await
+    })
+    .map_err(|e| {
+        error!("Failed to read the journal: {}", e);
+        Status::Misdirected
+    })?;
+    let mut names = Vec::new();
+    for entry in entries {
+        if entry.name == RESET {
+            return Ok(None);
+        }
+        if !names.contains(&entry.name) {
+            names.push(entry.name);
+        }
+    }
+    Ok(Some(names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::check_database;
+    use utils::storage::PoolSettings;
+
+    const PRIMARY: &str = "10.0.1.1:6202";
+
+    fn record(name: &str, ip: &str) -> Record {
+        Record {
+            name: name.to_owned(),
+            domain: Some((ip.to_owned(), 6204)),
+            dns: vec![],
+            ttl: 300,
+        }
+    }
+
+    async fn storage() -> Storage {
+        let settings = PoolSettings {
+            max_connections: 1,
+            ..PoolSettings::default()
+        };
+        let storage = Storage::connect("sqlite::memory:", &settings)
+            .await
+            .unwrap();
+        check_database(&storage, true).await;
+        storage
+    }
+
+    #[async_std::test]
+    async fn keeps_the_transferred_serial() {
+        let storage = storage().await;
+        assert_eq!(transferred_serial(&storage, PRIMARY).await, Ok(0));
+        let full = Transfer {
+            serial: 7,
+            full: true,
+            records: vec![record("example", "10.0.0.1"), record("shop", "10.0.0.2")],
+            deleted: vec![],
+        };
+        apply_transfer(&storage, PRIMARY, &full).await.unwrap();
+        assert_eq!(transferred_serial(&storage, PRIMARY).await, Ok(7));
+        let incremental = Transfer {
+            serial: 9,
+            full: false,
+            records: vec![record("example", "10.0.0.3")],
+            deleted: vec!["shop".to_owned()],
+        };
+        apply_transfer(&storage, PRIMARY, &incremental)
+            .await
+            .unwrap();
+        assert_eq!(transferred_serial(&storage, PRIMARY).await, Ok(9));
+        assert_eq!(
+            list(&storage).await.unwrap(),
+            vec![record("example", "10.0.0.3")]
+        );
+        // A serial from another primary is no place to carry on from.
+        assert_eq!(transferred_serial(&storage, "10.0.2.1:6202").await, Ok(0));
+    }
+
+    #[async_std::test]
+    async fn local_changes_forget_the_transferred_serial() {
+        let storage = storage().await;
+        let transfer = Transfer {
+            serial: 3,
+            full: true,
+            records: vec![record("example", "10.0.0.1")],
+            deleted: vec![],
+        };
+        apply_transfer(&storage, PRIMARY, &transfer).await.unwrap();
+        put(&storage, &record("shop", "10.0.0.2")).await.unwrap();
+        assert_eq!(transferred_serial(&storage, PRIMARY).await, Ok(0));
+        apply_transfer(&storage, PRIMARY, &transfer).await.unwrap();
+        assert_eq!(delete(&storage, "example").await, Ok(true));
+        assert_eq!(transferred_serial(&storage, PRIMARY).await, Ok(0));
+    }
+}
@@ -0,0 +1,302 @@
+//! Checking and creating the `dns_records` table, in either SQL dialect.
+//!
+//! Alongside it live `dns_delegations`, the DNS providers a name is delegated
+//! to after the first one, `dns_journal`, the names changed at each serial
+//! for zone transfers, and `dns_transfer_state`, the one row in which a
+//! secondary keeps the serial it last transferred. They are created whenever
+//! they are missing.
+
+use tracing::{error, info, trace, warn};
+use utils::{
+    sql_cols,
+    storage::{Dialect, Storage},
+    with_pool,
+};
+
+/// Columns in an up-to-date `dns_records` table.
+const COLUMNS: i32 = 7;
+
+struct Schema {
+    /// Counts the columns that match what `resolve` expects; `COLUMNS` when
+    /// all do.
+    check: &'static str,
+    create: &'static str,
+    /// Counts the `ttl` columns, for upgrading tables created without one.
+    has_ttl: &'static str,
+    add_ttl: &'static str,
+    /// Create `dns_delegations`, `dns_journal` and `dns_transfer_state` if
+    /// they do not exist.
+    auxiliary: [&'static str; 3],
+}
+
+const MYSQL: Schema = Schema {
+    check: r#"
+        SELECT
+            COUNT(*) as count
+        FROM
+            INFORMATION_SCHEMA.COLUMNS
+        WHERE
+            TABLE_NAME = 'dns_records'
+        AND (
+            (COLUMN_NAME = 'id' AND DATA_TYPE = 'int' AND IS_NULLABLE = 'NO')
+            OR (COLUMN_NAME = 'name' AND DATA_TYPE = 'varchar' AND CHARACTER_MAXIMUM_LENGTH = 255 AND IS_NULLABLE = 'NO')
+            OR (COLUMN_NAME = 'domain_ip' AND DATA_TYPE = 'varchar' AND CHARACTER_MAXIMUM_LENGTH = 63 AND IS_NULLABLE = 'YES')
+            OR (COLUMN_NAME = 'domain_port' AND DATA_TYPE = 'smallint' AND IS_NULLABLE = 'YES')
+            OR (COLUMN_NAME = 'dns_ip' AND DATA_TYPE = 'varchar' AND CHARACTER_MAXIMUM_LENGTH = 63 AND IS_NULLABLE = 'YES')
+            OR (COLUMN_NAME = 'dns_port' AND DATA_TYPE = 'smallint' AND IS_NULLABLE = 'YES')
+            OR (COLUMN_NAME = 'ttl' AND DATA_TYPE = 'int' AND IS_NULLABLE = 'NO')
+        );
+        "#,
+    create: r#"
+        CREATE TABLE IF NOT EXISTS dns_records (
+            id INT AUTO_INCREMENT PRIMARY KEY,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            domain_ip VARCHAR(63) NULL,
+            domain_port SMALLINT UNSIGNED NULL CHECK (domain_port BETWEEN 0 AND 25565),
+            dns_ip VARCHAR(63) NULL,
+            dns_port SMALLINT UNSIGNED NULL CHECK (dns_port BETWEEN 0 AND 25565),
+            ttl INT UNSIGNED NOT NULL DEFAULT 300
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+        "#,
+    has_ttl: r#"
+        SELECT
+            COUNT(*) as count
+        FROM
+            INFORMATION_SCHEMA.COLUMNS
+        WHERE
+            TABLE_NAME = 'dns_records'
+        AND COLUMN_NAME = 'ttl';
+        "#,
+    add_ttl: "ALTER TABLE dns_records ADD COLUMN ttl INT UNSIGNED NOT NULL DEFAULT 300;",
+    auxiliary: [
+        r#"
+        CREATE TABLE IF NOT EXISTS dns_delegations (
+            name VARCHAR(255) NOT NULL,
+            position SMALLINT UNSIGNED NOT NULL,
+            dns_ip VARCHAR(63) NOT NULL,
+            dns_port SMALLINT UNSIGNED NOT NULL CHECK (dns_port BETWEEN 0 AND 25565),
+            PRIMARY KEY (name, position)
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS dns_journal (
+            serial BIGINT AUTO_INCREMENT PRIMARY KEY,
+            name VARCHAR(255) NOT NULL
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS dns_transfer_state (
+            id TINYINT UNSIGNED PRIMARY KEY CHECK (id = 1),
+            source VARCHAR(255) NOT NULL,
+            serial BIGINT NOT NULL
+        ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_unicode_ci;
+        "#,
+    ],
+};
+
+const SQLITE: Schema = Schema {
+    check: r#"
+        SELECT
+            COUNT(*) as count
+        FROM
+            pragma_table_info('dns_records')
+        WHERE
+            (name = 'id' AND type = 'INTEGER' AND pk = 1)
+            OR (name = 'name' AND type = 'VARCHAR(255)' AND "notnull" = 1)
+            OR (name = 'domain_ip' AND type = 'VARCHAR(63)' AND "notnull" = 0)
+            OR (name = 'domain_port' AND type = 'INTEGER' AND "notnull" = 0)
+            OR (name = 'dns_ip' AND type = 'VARCHAR(63)' AND "notnull" = 0)
+            OR (name = 'dns_port' AND type = 'INTEGER' AND "notnull" = 0)
+            OR (name = 'ttl' AND type = 'INTEGER' AND "notnull" = 1);
+        "#,
+    create: r#"
+        CREATE TABLE IF NOT EXISTS dns_records (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR(255) UNIQUE NOT NULL,
+            domain_ip VARCHAR(63) NULL,
+            domain_port INTEGER NULL CHECK (domain_port BETWEEN 0 AND 25565),
+            dns_ip VARCHAR(63) NULL,
+            dns_port INTEGER NULL CHECK (dns_port BETWEEN 0 AND 25565),
+            ttl INTEGER NOT NULL DEFAULT 300 CHECK (ttl >= 0)
+        );
+        "#,
+    has_ttl: r#"
+        SELECT
+            COUNT(*) as count
+        FROM
+            pragma_table_info('dns_records')
+        WHERE
+            name = 'ttl';
+        "#,
+    add_ttl: "ALTER TABLE dns_records ADD COLUMN ttl INTEGER NOT NULL DEFAULT 300 CHECK (ttl >= 0);",
+    auxiliary: [
+        r#"
+        CREATE TABLE IF NOT EXISTS dns_delegations (
+            name VARCHAR(255) NOT NULL,
+            position INTEGER NOT NULL,
+            dns_ip VARCHAR(63) NOT NULL,
+            dns_port INTEGER NOT NULL CHECK (dns_port BETWEEN 0 AND 25565),
+            PRIMARY KEY (name, position)
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS dns_journal (
+            serial INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR(255) NOT NULL
+        );
+        "#,
+        r#"
+        CREATE TABLE IF NOT EXISTS dns_transfer_state (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            source VARCHAR(255) NOT NULL,
+            serial INTEGER NOT NULL
+        );
+        "#,
+    ],
+};
+
+fn schema(storage: &Storage) -> &'static Schema {
+    match storage.dialect() {
+        Dialect::MySql => &MYSQL,
+        Dialect::Sqlite => &SQLITE,
+    }
+}
+
+pub(crate) async fn check_database(storage: &Storage, overwrite: bool) {
+    trace!("Checking database schema integrity...");
+    add_missing_ttl(storage).await;
+    let check = with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Count>(schema(storage).check)
+            .fetch_optional(pool)
+            .await
+    });
+    match check {
+        Ok(Some(e)) => {
+            if e.count == COLUMNS {
+                trace!("Database schema integrity check passed.");
+            } else if overwrite {
+                warn!("Database schema mismatch. Will overwite.");
+                overwrite_database(storage).await;
+                trace!("Database schema overwritten.");
+            } else {
+                error!("Invalid database! Run with --overwrite to overwrite existing database.");
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            if overwrite {
+                warn!("Failure to locate database. Will overwite.");
+                overwrite_database(storage).await;
+                trace!("Database overwritten.");
+            } else {
+                error!(
+                    "Failed to locate databse! Run with --overwrite to generate a new database."
+                );
+                std::process::exit(1);
+            }
+        }
+    };
+    for create in schema(storage).auxiliary {
+        if let Err(e) = with_pool!(storage, |pool| {
+            sqlx::query(create).execute(pool).await.map(|_| ())
+        }) {
+            error!("Failed to create auxiliary tables: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn overwrite_database(storage: &Storage) {
+    let schema = schema(storage);
+    match with_pool!(storage, |pool| {
+        sqlx::query(schema.create).execute(pool).await.map(|_| ())
+    }) {
+        Ok(_) => {
+            info!("{} database successfully created.", storage.dialect());
+        }
+        Err(e) => {
+            error!("Failed to create database: {}", e);
+            std::process::exit(1);
+        }
+    };
+}
+
+/// Counts what `query` selects, or `None` if it fails.
+async fn count(storage: &Storage, query: &str) -> Option<i32> {
+    with_pool!(storage, |pool| {
+        sqlx::query_as::<_, sql_cols::Count>(query)
+            .fetch_optional(pool)
+            .await
+    })
+    .ok()
+    .flatten()
+    .map(|e| e.count)
+}
+
+/// Upgrades a table created before records carried a TTL in place, so that
+/// only real mismatches need `--overwrite`.
+async fn add_missing_ttl(storage: &Storage) {
+    let schema = schema(storage);
+    if count(storage, schema.has_ttl).await != Some(0)
+        || count(storage, schema.check).await != Some(COLUMNS - 1)
+    {
+        return;
+    }
+    match with_pool!(storage, |pool| {
+        sqlx::query(schema.add_ttl).execute(pool).await.map(|_| ())
+    }) {
+        Ok(_) => info!("Added ttl column to dns_records."),
+        Err(e) => {
+            error!("Failed to add ttl column: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::storage::PoolSettings;
+
+    #[async_std::test]
+    async fn upgrades_tables_without_ttl() {
+        let settings = PoolSettings {
+            max_connections: 1,
+            ..PoolSettings::default()
+        };
+        let storage = Storage::connect("sqlite::memory:", &settings)
+            .await
+            .unwrap();
+        let Storage::Sqlite(pool) = &storage else {
+            unreachable!()
+        };
+        sqlx::query(
+            r#"
+            CREATE TABLE dns_records (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name VARCHAR(255) UNIQUE NOT NULL,
+                domain_ip VARCHAR(63) NULL,
+                domain_port INTEGER NULL CHECK (domain_port BETWEEN 0 AND 25565),
+                dns_ip VARCHAR(63) NULL,
+                dns_port INTEGER NULL CHECK (dns_port BETWEEN 0 AND 25565)
+            );
+            "#,
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO dns_records (name, domain_ip, domain_port) VALUES ('a', '10.0.0.1', 80);",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        check_database(&storage, false).await;
+        assert_eq!(count(&storage, SQLITE.check).await, Some(COLUMNS));
+        let ttl: (i64,) = sqlx::query_as("SELECT ttl FROM dns_records WHERE name = 'a';")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(ttl.0, 300);
+    }
+}
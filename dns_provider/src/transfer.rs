@@ -0,0 +1,138 @@
+//! Zone transfers between a primary provider and its secondaries.
+//!
+//! A secondary asks its primary for every change since the serial it last
+//! applied, and then again every refresh interval. That serial is kept in
+//! `dns_transfer_state`, so only a secondary new to its primary, or whose
+//! records were changed by hand, starts from 0 and the whole zone. The
+//! primary answers from `dns_journal`, or with the whole zone when the
+//! journal no longer reaches back that far.
+
+use crate::records::{apply_transfer, changed_since, get, list, serial, transferred_serial};
+use async_std::task;
+use futures_rustls::TlsConnector;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+use utils::{
+    Status,
+    auth::Key,
+    framing::{FrameLimits, receive_data_async, send_data_async},
+    handshake::{Capabilities, client_handshake},
+    protocol::{
+        admin::{Command, Response, Transfer},
+        provider::Request,
+    },
+    storage::Storage,
+    tls,
+};
+
+const TRANSFER_LIMITS: FrameLimits =
+    FrameLimits::new(64 * 1024 * 1024, Some(Duration::from_secs(30)));
+
+/// The provider a secondary copies its records from.
+pub(crate) struct Primary {
+    /// Address of the primary, as `host:port`.
+    pub(crate) address: String,
+    /// An unrestricted admin key the primary knows.
+    pub(crate) key: Key,
+    /// How often to ask for changes.
+    pub(crate) refresh: Duration,
+    /// Set when the primary is reached over TLS.
+    pub(crate) tls: Option<TlsConnector>,
+}
+
+/// What a secondary at serial `since` needs to catch up.
+pub(crate) async fn changes(storage: &Storage, since: u64) -> Result<Transfer, Status> {
+    // Read first, so changes made meanwhile are sent again next time rather
+    // than skipped.
+    let serial = serial(storage).await?;
+    let changed = if since == 0 || since > serial {
+        None
+    } else {
+        changed_since(storage, since).await?
+    };
+    let Some(names) = changed else {
+        return Ok(Transfer {
+            serial,
+            full: true,
+            records: list(storage).await?,
+            deleted: Vec::new(),
+        });
+    };
+    let mut records = Vec::new();
+    let mut deleted = Vec::new();
+    for name in names {
+        match get(storage, &name).await? {
+            Some(record) => records.push(record),
+            None => deleted.push(name),
+        }
+    }
+    Ok(Transfer {
+        serial,
+        full: false,
+        records,
+        deleted,
+    })
+}
+
+/// Keeps `storage` in step with `primary`, forever.
+pub(crate) async fn follow_primary(storage: Storage, primary: Primary) {
+    let mut serial = transferred_serial(&storage, &primary.address)
+        .await
+        .unwrap_or_default();
+    if serial > 0 {
+        info!("Carrying on from {} at serial {}.", primary.address, serial);
+    }
+    loop {
+        match pull(&primary, serial).await {
+            Ok(transfer) if transfer.serial == serial && !transfer.full => {
+                debug!("Up to date with {} at serial {}.", primary.address, serial);
+            }
+            Ok(transfer) => match apply_transfer(&storage, &primary.address, &transfer).await {
+                Ok(()) => {
+                    info!(
+                        "{} transfer from {}: {} records, {} deleted, now at serial {}.",
+                        if transfer.full { "Full" } else { "Incremental" },
+                        primary.address,
+                        transfer.records.len(),
+                        transfer.deleted.len(),
+                        transfer.serial
+                    );
+                    serial = transfer.serial;
+                }
+                Err(status) => warn!(
+                    "Failed to apply transfer from {}: {}",
+                    primary.address, status
+                ),
+            },
+            Err(e) => warn!("Failed to transfer zone from {}: {}", primary.address, e),
+        }
+        task::sleep(primary.refresh).await;
+    }
+}
+
+/// Asks `primary` for every change since `since`.
+async fn pull(primary: &Primary, since: u64) -> Result<Transfer, String> {
+    let signed = primary
+        .key
+        .sign(Command::Transfer { since })
+        .map_err(|e| e.to_string())?;
+    let request = Request::Manage(signed)
+        .encode()
+        .map_err(|e| e.to_string())?;
+    let mut stream = tls::connect(&primary.address, primary.tls.as_ref(), &TRANSFER_LIMITS)
+        .await
+        .map_err(|e| format!("failed to connect: {e}"))?;
+    client_handshake(&mut stream, Capabilities::NONE, &TRANSFER_LIMITS)
+        .await
+        .map_err(|e| e.to_string())?;
+    send_data_async(&request, &mut stream)
+        .await
+        .map_err(|e| format!("failed to send request: {e}"))?;
+    let data = receive_data_async(&mut stream, &TRANSFER_LIMITS)
+        .await
+        .map_err(|e| format!("failed to receive response: {e}"))?;
+    match Response::decode(&data).map_err(|e| e.to_string())? {
+        Response::Transfer(transfer) => Ok(transfer),
+        response => Err(response.status().to_string()),
+    }
+}
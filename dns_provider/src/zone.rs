@@ -0,0 +1,338 @@
+//! Plain-text zone files holding the whole of `dns_records`.
+//!
+//! A zone file has one record per line, in four whitespace-separated columns,
+//! the same ones `dns_admin list` prints:
+//!
+//! ```text
+//! # name   ttl   domain          dns
+//! .        300   10.0.0.1:6204   -
+//! example  60    10.0.0.2:6204   -
+//! shop     300   -               10.0.1.1:6202,10.0.2.1:6202
+//! super    120   10.0.0.3:6204   10.0.1.2:6202
+//! ```
+//!
+//! - `name` is one block of an FQDN without dots, answered by this provider,
+//!   or `.` for the record that applies to the provider itself.
+//! - `ttl` is how many seconds clients may keep the answer.
+//! - `domain` is the `ip:port` of the site with that name, or `-` for none.
+//!   On the `.` record it is where names without a record are sent.
+//! - `dns` is the `ip:port` of the DNS provider that names below this one
+//!   are delegated to, or `-` for none. Secondaries follow after commas, and
+//!   clients that can fail over try them in order. On the `.` record it moves
+//!   the whole provider there.
+//!
+//! Every record needs a `domain`, a `dns` or both, and each name may appear
+//! only once. Anything after a `#` is a comment, and blank lines are skipped.
+//!
+//! Importing replaces every record in the database with those in the file, in
+//! one transaction, and only once the whole file has been read without error.
+//! Secondaries following this provider transfer the whole zone again after.
+
+use crate::records::{list, replace_all, validate};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Read, Write},
+};
+use utils::{protocol::admin::Record, storage::Storage};
+
+/// Something wrong with a zone file, or with reading or writing one.
+#[derive(Debug)]
+pub(crate) enum ZoneError {
+    Io(io::Error),
+    Line { line: usize, reason: String },
+    Database(String),
+}
+
+impl fmt::Display for ZoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZoneError::Io(e) => write!(f, "{e}"),
+            ZoneError::Line { line, reason } => write!(f, "line {line}: {reason}"),
+            ZoneError::Database(reason) => write!(f, "database error: {reason}"),
+        }
+    }
+}
+
+/// Reads the zone in `path`, `-` for stdin, and makes it the content of
+/// `dns_records`. Returns the number of records imported, or every error
+/// found in the file.
+pub(crate) async fn import(storage: &Storage, path: &str) -> Result<usize, Vec<ZoneError>> {
+    let text = read(path).map_err(|e| vec![ZoneError::Io(e)])?;
+    let records = parse(&text)?;
+    replace_all(storage, &records)
+        .await
+        .map_err(|status| vec![ZoneError::Database(status.to_string())])?;
+    Ok(records.len())
+}
+
+/// Writes every record to `path`, `-` for stdout, as a zone file. Returns
+/// the number of records exported.
+pub(crate) async fn export(storage: &Storage, path: &str) -> Result<usize, ZoneError> {
+    let records = list(storage)
+        .await
+        .map_err(|status| ZoneError::Database(status.to_string()))?;
+    let mut text = "# name\tttl\tdomain\tdns\n".to_owned();
+    for record in &records {
+        text += &format!("{record}\n");
+    }
+    let written = if path == "-" {
+        io::stdout().write_all(text.as_bytes())
+    } else {
+        fs::write(path, text)
+    };
+    written.map_err(ZoneError::Io)?;
+    Ok(records.len())
+}
+
+fn read(path: &str) -> io::Result<String> {
+    if path == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+/// Parses and validates a whole zone, collecting every error on the way.
+fn parse(text: &str) -> Result<Vec<Record>, Vec<ZoneError>> {
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    let mut defined = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_record(line).and_then(|record| validate(&record).map(|_| record)) {
+            Ok(record) => match defined.get(&record.name) {
+                Some(first) => errors.push(ZoneError::Line {
+                    line: line_number,
+                    reason: format!("{} is already defined on line {}", record.name, first),
+                }),
+                None => {
+                    defined.insert(record.name.clone(), line_number);
+                    records.push(record);
+                }
+            },
+            Err(reason) => errors.push(ZoneError::Line {
+                line: line_number,
+                reason,
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(records)
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_record(line: &str) -> Result<Record, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [name, ttl, domain, dns] = fields[..] else {
+        return Err(format!(
+            "expected 4 columns (name, ttl, domain, dns), found {}",
+            fields.len()
+        ));
+    };
+    Ok(Record {
+        name: name.to_owned(),
+        domain: parse_address(domain)?,
+        dns: match dns {
+            "-" => Vec::new(),
+            dns => dns
+                .split(',')
+                .map(|address| {
+                    parse_address(address)
+                        .ok()
+                        .flatten()
+                        .ok_or_else(|| dns_error(dns))
+                })
+                .collect::<Result<_, _>>()?,
+        },
+        ttl: ttl
+            .parse()
+            .map_err(|_| format!("ttl {ttl:?} is not a number of seconds"))?,
+    })
+}
+
+fn parse_address(address: &str) -> Result<Option<(String, u16)>, String> {
+    if address == "-" {
+        return Ok(None);
+    }
+    address
+        .rsplit_once(':')
+        .and_then(|(ip, port)| Some((ip.to_owned(), port.parse().ok()?)))
+        .map(Some)
+        .ok_or_else(|| format!("expected ip:port or -, found {address:?}"))
+}
+
+fn dns_error(dns: &str) -> String {
+    format!("expected ip:port separated by commas, or -, found {dns:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::check_database;
+    use std::{env, path::PathBuf, process};
+    use utils::storage::PoolSettings;
+
+    const ZONE: &str = "\
+# name   ttl   domain          dns
+.        300   10.0.0.1:6204   -
+example  60    10.0.0.2:6204   -   # trailing comment
+
+shop     300   -               10.0.1.1:6202,10.0.2.1:6202
+super    120   10.0.0.3:6204   10.0.1.2:6202
+";
+
+    fn record(name: &str, ttl: u32, domain: Option<&str>, dns: &[&str]) -> Record {
+        let address = |address: &str| {
+            let (ip, port) = address.rsplit_once(':').unwrap();
+            (ip.to_owned(), port.parse().unwrap())
+        };
+        Record {
+            name: name.to_owned(),
+            domain: domain.map(address),
+            dns: dns.iter().copied().map(address).collect(),
+            ttl,
+        }
+    }
+
+    /// The line and reason of every error `parse` finds in `text`.
+    fn errors(text: &str) -> Vec<(usize, String)> {
+        parse(text)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| match e {
+                ZoneError::Line { line, reason } => (line, reason),
+                e => panic!("unexpected error {e}"),
+            })
+            .collect()
+    }
+
+    fn zone_file(test: &str) -> PathBuf {
+        env::temp_dir().join(format!("zone-test-{}-{test}.zone", process::id()))
+    }
+
+    #[test]
+    fn parses_a_valid_zone() {
+        assert_eq!(
+            parse(ZONE).unwrap(),
+            vec![
+                record(".", 300, Some("10.0.0.1:6204"), &[]),
+                record("example", 60, Some("10.0.0.2:6204"), &[]),
+                record("shop", 300, None, &["10.0.1.1:6202", "10.0.2.1:6202"]),
+                record("super", 120, Some("10.0.0.3:6204"), &["10.0.1.2:6202"]),
+            ]
+        );
+        assert_eq!(parse("# nothing but comments\n\n").unwrap(), vec![]);
+    }
+
+    #[async_std::test]
+    async fn import_export_round_trip() {
+        let settings = PoolSettings {
+            max_connections: 1,
+            ..PoolSettings::default()
+        };
+        let storage = Storage::connect("sqlite::memory:", &settings)
+            .await
+            .unwrap();
+        check_database(&storage, true).await;
+        let (original, exported) = (zone_file("original"), zone_file("exported"));
+        fs::write(&original, ZONE).unwrap();
+        assert_eq!(
+            import(&storage, original.to_str().unwrap()).await.unwrap(),
+            4
+        );
+        assert_eq!(
+            export(&storage, exported.to_str().unwrap()).await.unwrap(),
+            4
+        );
+        let text = fs::read_to_string(&exported).unwrap();
+        assert_eq!(parse(&text).unwrap(), parse(ZONE).unwrap());
+        // Importing the export again changes nothing.
+        assert_eq!(
+            import(&storage, exported.to_str().unwrap()).await.unwrap(),
+            4
+        );
+        assert_eq!(list(&storage).await.unwrap(), parse(ZONE).unwrap());
+        fs::remove_file(original).unwrap();
+        fs::remove_file(exported).unwrap();
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let text =
+            "example 60 10.0.0.2:6204 -\nshop 60 10.0.0.3:6204 -\nexample 30 - 10.0.1.1:6202\n";
+        assert_eq!(
+            errors(text),
+            vec![(3, "example is already defined on line 1".to_owned())]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_addresses_and_ports() {
+        let text = "\
+a 60 10.0.0.1 -
+b 60 10.0.0.1:99999 -
+c 60 10.0.0.1:30000 -
+d 60 - 10.0.1.1:6202,-
+e 60 - 10.0.1.1:6202,10.0.1.2
+f 60 - -
+g sixty 10.0.0.1:80 -
+h 60 10.0.0.1:80
+";
+        assert_eq!(
+            errors(text),
+            vec![
+                (1, "expected ip:port or -, found \"10.0.0.1\"".to_owned()),
+                (
+                    2,
+                    "expected ip:port or -, found \"10.0.0.1:99999\"".to_owned()
+                ),
+                (3, "port 30000 is above 25565".to_owned()),
+                (4, dns_error("10.0.1.1:6202,-")),
+                (5, dns_error("10.0.1.1:6202,10.0.1.2")),
+                (6, "f has neither a domain nor a DNS address".to_owned()),
+                (7, "ttl \"sixty\" is not a number of seconds".to_owned()),
+                (
+                    8,
+                    "expected 4 columns (name, ttl, domain, dns), found 3".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_line_numbers_of_the_file() {
+        let text = "\
+# header
+
+ok 60 10.0.0.1:80 -
+   # indented comment
+bad.name 60 10.0.0.1:80 -
+
+ok 60 10.0.0.2:80 -
+";
+        assert_eq!(
+            errors(text),
+            vec![
+                (5, "name \"bad.name\" is not a single block".to_owned()),
+                (7, "ok is already defined on line 3".to_owned()),
+            ]
+        );
+        assert_eq!(
+            parse("a 60 10.0.0.1:80 -\nb 60 bogus -")
+                .unwrap_err()
+                .iter()
+                .map(ZoneError::to_string)
+                .collect::<Vec<_>>(),
+            vec!["line 2: expected ip:port or -, found \"bogus\""]
+        );
+    }
+}
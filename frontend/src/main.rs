@@ -1,55 +1,154 @@
 use async_std::io;
-use backend::{dns_task, get_stack_info, parse_stack, resolve};
+use backend::{ResolverConfig, dns_task, get_stack_info, parse_stack, resolve};
 use gtk::{Application, ApplicationWindow, gdk, glib, prelude::*};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
-use std::{env, fs, net::TcpStream, path};
+use std::{
+    cell::RefCell,
+    fs, path,
+    rc::Rc,
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tracing::{debug, error, info, trace, warn};
 use utils::{
-    fqdn_to_upe, get_config_dir, receive_data, send_data, sql_cols, status, trace_subscription,
+    Status,
+    compression::{Compression, receive_message_async, send_message_async},
+    config::{Config, Opt, Spec},
+    framing::FrameLimits,
+    get_config_dir,
+    handshake::{Capabilities, client_handshake},
+    protocol::{
+        STACK_LEN,
+        content::{Request, Response},
+    },
+    sql_cols, tls, trace_subscription,
+    url::WebUrl,
 };
 const APP_ID: &str = "dither.browser";
 const PROJ_NAME: &str = "Browser";
+const SPEC: Spec = Spec {
+    program: "frontend",
+    about: "Browses web:// sites.",
+    applet: PROJ_NAME,
+    env_prefix: "BROWSER",
+    options: &[
+        Opt::flag(
+            "data-saver",
+            Some('d'),
+            "Only load pages when a search is submitted, and ask for them compressed",
+        ),
+        Opt::flag(
+            "no-caching",
+            Some('c'),
+            "Resolve every address instead of using the cache",
+        ),
+        Opt::flag("refresh-stacks", Some('r'), "Rebuild the stacks database"),
+        Opt::value(
+            "stacks",
+            Some('S'),
+            "STACKS",
+            "Stacks to request, in order [default: all installed]",
+        ),
+        Opt::list(
+            "add-stacks",
+            Some('s'),
+            "STACKS",
+            "Append to the stacks to request",
+        ),
+        Opt::value(
+            "tls-ca",
+            None,
+            "FILE",
+            "PEM certificates to trust; enables TLS",
+        ),
+        Opt::list(
+            "provider",
+            None,
+            "ADDRESS",
+            "Root DNS providers to resolve names with, in order",
+        )
+        .default("0.0.0.0:6202"),
+        Opt::list("cacher", None, "ADDRESS", "DNS cachers to ask, in order")
+            .default("0.0.0.0:6203"),
+        Opt::value(
+            "strategy",
+            None,
+            "STRATEGY",
+            "Use several providers or cachers by failover or in parallel",
+        )
+        .default("failover"),
+        Opt::flag(
+            "integrity-check",
+            None,
+            "Compare cacher answers with the providers and report stale ones",
+        ),
+        Opt::value(
+            "timeout",
+            None,
+            "SECONDS",
+            "Give up resolving an address after this long",
+        )
+        .default("15"),
+        Opt::count("verbose", Some('v'), "Log more; repeat for trace output"),
+    ],
+};
+
+/// Set once from the configuration before the window opens.
+static RESOLVERS: OnceLock<ResolverConfig> = OnceLock::new();
 
 #[async_std::main]
 async fn main() -> glib::ExitCode {
-    let mut verbose_level = 0u8;
-    let mut caching = true;
-    let mut force_stacks_refresh = false;
-    let mut data_saver = false;
-    let mut stacks = String::new();
-    let args: Vec<String> = env::args().collect();
-    for (i, arg) in args.iter().enumerate() {
-        if arg.starts_with("--") {
-            match arg.strip_prefix("--").unwrap_or_default() {
-                "data-saver" => data_saver = true,
-                "no-caching" => caching = false,
-                "refresh-stacks" => force_stacks_refresh = true,
-                "stacks" => stacks = args[i + 1].clone(),
-                "verbose" => verbose_level += 1,
-                _ => panic!("Pre-init failure; unknown long-name argument: {arg}"),
-            }
-        } else if arg.starts_with("-") {
-            let mut argindex = i;
-            for char in arg.strip_prefix("-").unwrap_or_default().chars() {
-                match char {
-                    'c' => caching = false,
-                    'd' => data_saver = true,
-                    'r' => force_stacks_refresh = true,
-                    'S' => {
-                        stacks = args[argindex + 1].clone();
-                        argindex += 1;
-                    }
-                    's' => {
-                        stacks += &args[argindex + 1].clone();
-                        argindex += 1;
-                    }
-                    'v' => verbose_level += 1,
-                    _ => panic!("Pre-init failure; unknown short-name argument: {arg}"),
-                }
-            }
+    let config = Config::load_or_exit(&SPEC);
+    trace_subscription(config.count("verbose"));
+    let mut caching = !config.flag("no-caching");
+    let force_stacks_refresh = config.flag("refresh-stacks");
+    let data_saver = config.flag("data-saver");
+    let mut stacks = config.get("stacks").unwrap_or_default().to_owned();
+    stacks.extend(config.list("add-stacks"));
+    let tls = match config
+        .get("tls-ca")
+        .map(path::Path::new)
+        .map(tls::client_connector)
+    {
+        None => None,
+        Some(Ok(connector)) => Some(connector),
+        Some(Err(e)) => {
+            error!("Failed to set up TLS: {}", e);
+            return glib::ExitCode::FAILURE;
         }
-    }
-    trace_subscription(verbose_level);
+    };
+    let strategy = match config.require("strategy") {
+        Ok(strategy) => strategy,
+        Err(e) => {
+            error!("{}", e);
+            return glib::ExitCode::FAILURE;
+        }
+    };
+    let timeout = match config.require("timeout") {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(e) => {
+            error!("{}", e);
+            return glib::ExitCode::FAILURE;
+        }
+    };
+    let resolvers = ResolverConfig {
+        providers: config
+            .list("provider")
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+        cachers: config
+            .list("cacher")
+            .into_iter()
+            .map(str::to_owned)
+            .collect(),
+        strategy,
+        integrity_check: config.flag("integrity-check"),
+        timeout,
+        tls,
+    };
+    debug!("Resolving with {:?}", resolvers);
+    let _ = RESOLVERS.set(resolvers);
     if !compile_stacks(force_stacks_refresh).await {
         return glib::ExitCode::FAILURE;
     }
@@ -72,6 +171,12 @@ async fn main() -> glib::ExitCode {
 
 fn build_ui(app: &Application, caching: bool, data_saver: bool, stacks: String) {
     load_css();
+    // Compressing costs the server and us time, so only ask when saving data.
+    let compression = if data_saver {
+        Compression::ALL
+    } else {
+        Capabilities::NONE
+    };
     let mainbox = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
         .build();
@@ -187,8 +292,13 @@ fn build_ui(app: &Application, caching: bool, data_saver: bool, stacks: String)
                                 }
                                 if let Some(entry) = entry_weak.upgrade() {
                                     searchbar.set_css_classes(&["yellowsearch"]);
-                                    let buffer =
-                                        try_get_webpage(&entry, caching, &stacks_clone).await;
+                                    let buffer = try_get_webpage(
+                                        &entry,
+                                        caching,
+                                        &stacks_clone,
+                                        compression,
+                                    )
+                                    .await;
                                     present_cached_webpage(
                                         buffer,
                                         &searchbar,
@@ -207,14 +317,15 @@ fn build_ui(app: &Application, caching: bool, data_saver: bool, stacks: String)
                             }
                             PageContent::Failure(e) => {
                                 error!("FS error: {}", e);
-                                scrolledwindow.set_child(Some(&no_webpage(status::SHAT_THE_BED)));
+                                scrolledwindow.set_child(Some(&no_webpage(Status::ShatTheBed)));
                                 searchbar.set_search_mode(false);
                             }
                             _ => searchbar.set_css_classes(&["yellowsearch"]),
                         }
                     } else if let Some(entry) = entry_weak.upgrade() {
                         searchbar.set_css_classes(&["yellowsearch"]);
-                        let buffer = try_get_webpage(&entry, caching, &stacks_clone).await;
+                        let buffer =
+                            try_get_webpage(&entry, caching, &stacks_clone, compression).await;
                         present_cached_webpage(buffer, &searchbar, &scrolledwindow, &pagecontent);
                     }
                 });
@@ -223,12 +334,17 @@ fn build_ui(app: &Application, caching: bool, data_saver: bool, stacks: String)
     });
     let sw_weak = scrolled_window.downgrade();
     let sb_weak = search_bar.downgrade();
+    // The preload for the previous text, dropped once the text changes again.
+    let preload: Rc<RefCell<Option<glib::JoinHandle<()>>>> = Rc::default();
     entry.connect_changed(move |entry| {
         let sw_weak = sw_weak.clone();
         let sb_weak = sb_weak.clone();
         let entry_weak = entry.downgrade();
         let stacks_clone = stacks.clone();
-        glib::MainContext::default().spawn_local(async move {
+        if let Some(previous) = preload.borrow_mut().take() {
+            previous.abort();
+        }
+        let handle = glib::MainContext::default().spawn_local(async move {
             if let Some(entry) = entry_weak.upgrade() {
                 if let Some(searchbar) = sb_weak.upgrade() {
                     searchbar.set_css_classes(&[""]);
@@ -255,12 +371,14 @@ fn build_ui(app: &Application, caching: bool, data_saver: bool, stacks: String)
                         if content == Some(PageContent::Paused) {
                             return;
                         }
-                        let buffer = try_get_webpage(&entry, caching, &stacks_clone).await;
+                        let buffer =
+                            try_get_webpage(&entry, caching, &stacks_clone, compression).await;
                         pre_load_webpage(buffer, &searchbar, &scrolledwindow);
                     }
                 }
             }
         });
+        preload.replace(Some(handle));
     });
     let sw_weak = scrolled_window.downgrade();
     let sb_weak = search_bar.downgrade();
@@ -302,14 +420,13 @@ fn pre_load_webpage(
     scrolledwindow: &gtk::ScrolledWindow,
 ) {
     match &buffer {
-        PageContent::Page(pagedata) => match pagedata.1 {
-            status::SUCCESS => {
+        PageContent::Page(pagedata) => {
+            if pagedata.1.is_success() {
                 searchbar.set_css_classes(&["greensearch"]);
-            }
-            _ => {
+            } else {
                 searchbar.set_css_classes(&["redsearch"]);
             }
-        },
+        }
         PageContent::Nothing => {}
         _ => {
             searchbar.set_css_classes(&["redsearch"]);
@@ -337,7 +454,7 @@ fn present_cached_webpage(
         }
         PageContent::Failure(e) => {
             error!("FS error: {}", e);
-            scrolledwindow.set_child(Some(&no_webpage(status::SHAT_THE_BED)));
+            scrolledwindow.set_child(Some(&no_webpage(Status::ShatTheBed)));
             searchbar.set_search_mode(false);
         }
         _ => searchbar.set_css_classes(&["yellowsearch"]),
@@ -349,20 +466,34 @@ fn present_cached_webpage(
     }
 }
 
-async fn try_get_webpage(entry: &gtk::SearchEntry, caching: bool, stacks: &str) -> PageContent {
+async fn try_get_webpage(
+    entry: &gtk::SearchEntry,
+    caching: bool,
+    stacks: &str,
+    compression: Capabilities,
+) -> PageContent {
     if entry.text().is_empty() {
         return PageContent::Nothing;
     }
-    let mut statuscode = status::HOST_UNREACHABLE;
-    let (url, port, endpoint) = fqdn_to_upe(&entry.text());
+    let mut statuscode = Status::HostUnreachable;
+    let web_url = match entry.text().parse::<WebUrl>() {
+        Ok(web_url) => web_url,
+        Err(e) => {
+            warn!("Invalid URL {}: {}", entry.text(), e);
+            return PageContent::Status(Status::BadRequest);
+        }
+    };
+    let url = web_url.host().to_string();
+    let port = web_url.port();
+    let endpoint = web_url.target();
     let mut webview = None;
-    trace!("URL: {url}, Port: {port:?}, Endpoint: {endpoint}");
+    trace!("URL: {web_url}");
     if !caching {
         trace!("Caching disabled. Will resolve directly.");
         let res = resolve_url(&url).await;
         statuscode = res.1;
         if let Some(ip) = res.0 {
-            webview = Some(draw_webpage((ip, endpoint), stacks));
+            webview = Some(draw_webpage((ip, endpoint), stacks, compression));
         }
     } else {
         let config_dir = match get_config_dir(PROJ_NAME) {
@@ -394,9 +525,17 @@ async fn try_get_webpage(entry: &gtk::SearchEntry, caching: bool, stacks: &str)
             }
         };
         trace!("Successfully connected to database");
+        if let Err(e) = sqlx::query("DELETE FROM ephemeral WHERE expires IS NULL OR expires <= ?;")
+            .bind(unix_now())
+            .execute(&pool)
+            .await
+        {
+            error!("Database error: {}", e);
+        }
         let mut blocks = url.split('.').collect::<Vec<_>>();
         let mut lookahead = String::new();
         let mut verified_url = None;
+        let mut ttl = Duration::ZERO;
         let mut cache_used = false;
         while !blocks.is_empty() {
             trace!("Blocks: {:?}", blocks);
@@ -414,7 +553,8 @@ async fn try_get_webpage(entry: &gtk::SearchEntry, caching: bool, stacks: &str)
                 let dest = if lookahead.is_empty() {
                     Some(ip)
                 } else {
-                    let res = dns_task(&ip, &lookahead).await;
+                    let tls = RESOLVERS.get().and_then(|r| r.tls.as_ref());
+                    let res = dns_task(&ip, &lookahead, tls).await;
                     res.0
                 };
                 let dest = if dest.is_some() && port.is_some() {
@@ -428,9 +568,13 @@ async fn try_get_webpage(entry: &gtk::SearchEntry, caching: bool, stacks: &str)
                 };
                 match dest {
                     Some(dest) => {
-                        webview = Some(draw_webpage((dest.clone(), endpoint.clone()), stacks));
+                        webview = Some(draw_webpage(
+                            (dest.clone(), endpoint.clone()),
+                            stacks,
+                            compression,
+                        ));
                         verified_url = Some(dest.clone());
-                        let res = resolve_url(&entry.text()).await;
+                        let res = resolve_url(&url).await;
                         statuscode = res.1;
                         if let Some(validated_url) = res.0 {
                             if dest != validated_url {
@@ -452,19 +596,25 @@ async fn try_get_webpage(entry: &gtk::SearchEntry, caching: bool, stacks: &str)
                                 webview = Some(draw_webpage(
                                     (validated_url.clone(), endpoint.clone()),
                                     stacks,
+                                    compression,
                                 ));
                                 verified_url = Some(validated_url);
+                                ttl = res.2;
                             }
                         };
                     }
                     None => {
                         error!("Exhausted all attempts to resolve url!");
-                        let res = resolve_url(&entry.text()).await;
+                        let res = resolve_url(&url).await;
                         statuscode = res.1;
                         verified_url = res.0;
+                        ttl = res.2;
                         if let Some(dest) = &verified_url {
-                            webview =
-                                Some(draw_webpage((dest.to_string(), endpoint.clone()), stacks));
+                            webview = Some(draw_webpage(
+                                (dest.to_string(), endpoint.clone()),
+                                stacks,
+                                compression,
+                            ));
                         }
                     }
                 }
@@ -483,21 +633,29 @@ async fn try_get_webpage(entry: &gtk::SearchEntry, caching: bool, stacks: &str)
         if blocks.is_empty() {
             warn!("No cache found for {}!", url);
             debug!("resolving {} directly...", url);
-            let res = resolve_url(&entry.text()).await;
+            let res = resolve_url(&url).await;
             statuscode = res.1;
             verified_url = res.0;
+            ttl = res.2;
             if let Some(dest) = &verified_url {
-                webview = Some(draw_webpage((dest.to_string(), endpoint), stacks));
+                webview = Some(draw_webpage(
+                    (dest.to_string(), endpoint),
+                    stacks,
+                    compression,
+                ));
             }
             lookahead = String::new();
         }
-        if lookahead.is_empty() && verified_url.is_some() && !cache_used {
-            debug!("Caching resolved url");
-            match sqlx::query("INSERT INTO ephemeral (url, ip) VALUES (?, ?);")
-                .bind(url)
-                .bind(verified_url.as_ref().unwrap())
-                .execute(&pool)
-                .await
+        if lookahead.is_empty() && verified_url.is_some() && !cache_used && !ttl.is_zero() {
+            debug!("Caching resolved url for {}s", ttl.as_secs());
+            match sqlx::query(
+                "INSERT OR REPLACE INTO ephemeral (url, ip, expires) VALUES (?, ?, ?);",
+            )
+            .bind(url)
+            .bind(verified_url.as_ref().unwrap())
+            .bind(unix_now().saturating_add_unsigned(ttl.as_secs()))
+            .execute(&pool)
+            .await
             {
                 Ok(_) => {}
                 Err(e) => {
@@ -516,13 +674,21 @@ async fn try_get_webpage(entry: &gtk::SearchEntry, caching: bool, stacks: &str)
     PageContent::Status(statuscode)
 }
 
-async fn resolve_url(destination: &str) -> (Option<String>, u32) {
-    let ip = resolve(destination, None, None, None).await;
-    if ip.0.is_empty() {
-        (None, ip.1)
-    } else {
-        (Some(ip.0), ip.1)
+async fn resolve_url(destination: &str) -> (Option<String>, Status, Duration) {
+    let resolution = resolve(destination, RESOLVERS.get_or_init(ResolverConfig::default)).await;
+    if let Some(source) = resolution.source {
+        debug!("{} answered for {}", source, destination);
+    }
+    for hop in &resolution.hops {
+        debug!("  {}", hop);
     }
+    (resolution.address, resolution.status, resolution.ttl)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64)
 }
 
 async fn create_cache() -> bool {
@@ -559,7 +725,8 @@ async fn create_cache() -> bool {
         CREATE TABLE IF NOT EXISTS ephemeral
             (id INTEGER PRIMARY KEY AUTOINCREMENT,
             url TEXT UNIQUE NOT NULL,
-            ip TEXT NOT NULL);
+            ip TEXT NOT NULL,
+            expires INTEGER);
         "#,
     )
     .execute(&pool)
@@ -571,6 +738,21 @@ async fn create_cache() -> bool {
             return false;
         }
     }
+    // Caches from before entries expired are upgraded in place; their old
+    // entries have no expiry and are dropped on the next lookup.
+    if let Ok(Some(e)) = sqlx::query_as::<_, sql_cols::Count>(
+        "SELECT COUNT(*) as count FROM pragma_table_info('ephemeral') WHERE name = 'expires';",
+    )
+    .fetch_optional(&pool)
+    .await
+        && e.count == 0
+        && let Err(e) = sqlx::query("ALTER TABLE ephemeral ADD COLUMN expires INTEGER;")
+            .execute(&pool)
+            .await
+    {
+        error!("Database error: {}", e);
+        return false;
+    }
     true
 }
 
@@ -711,8 +893,12 @@ async fn list_stacks() -> String {
     stacks
 }
 
-async fn draw_webpage(address: (String, String), stacks: &str) -> (Option<gtk::Box>, u32) {
-    let res = get_data(&address, stacks);
+async fn draw_webpage(
+    address: (String, String),
+    stacks: &str,
+    compression: Capabilities,
+) -> (Option<gtk::Box>, Status) {
+    let res = get_data(&address, stacks, compression).await;
     match res.0 {
         Some(data) => (
             parse_stack(&String::from_utf8_lossy(&data.0), &data.1, PROJ_NAME).await,
@@ -722,15 +908,16 @@ async fn draw_webpage(address: (String, String), stacks: &str) -> (Option<gtk::B
     }
 }
 
-fn no_webpage(err: u32) -> gtk::Box {
+fn no_webpage(err: Status) -> gtk::Box {
+    let severity = if err.is_server_error() {
+        "error"
+    } else {
+        "warning"
+    };
     let label = gtk::Label::builder()
-        .label(format!(
-            "Error retrieving webpage! {}: {}",
-            err,
-            status::decode(&err)
-        ))
+        .label(format!("Error retrieving webpage! {err}"))
         .vexpand(true)
-        .css_classes(["title-1", "warning"])
+        .css_classes(["title-1", severity])
         .build();
     let webview = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
@@ -739,62 +926,73 @@ fn no_webpage(err: u32) -> gtk::Box {
     webview
 }
 
-fn get_data(address: &(String, String), stacks: &str) -> (Option<(Vec<u8>, String)>, u32) {
-    let mut statuscode = status::HOST_UNREACHABLE;
-    let program_version: Vec<u32> = env!("CARGO_PKG_VERSION")
-        .split('.')
-        .map(|f| match f.parse::<u32>() {
-            Ok(version) => version,
+/// Fetches the page at `address`, offering the server the `compression`
+/// algorithms.
+async fn get_data(
+    address: &(String, String),
+    stacks: &str,
+    compression: Capabilities,
+) -> (Option<(Vec<u8>, String)>, Status) {
+    let connector = RESOLVERS.get().and_then(|r| r.tls.as_ref());
+    let Ok(mut stream) = tls::connect(&address.0, connector, &FrameLimits::default()).await else {
+        error!("Failed to connect to {}!", &address.0);
+        return (None, Status::HostUnreachable);
+    };
+    let compression =
+        match client_handshake(&mut stream, compression, &FrameLimits::default()).await {
+            Ok(session) => session.compression(),
             Err(e) => {
-                panic!("Failed to parse version: {e}");
+                error!("Handshake with {} failed: {}", &address.0, e);
+                return (None, e.status());
             }
-        })
-        .collect();
-    assert!(program_version.len() > 2);
-    let Ok(stream) = TcpStream::connect(&address.0) else {
-        error!("Failed to connect to {}!", &address.0);
-        return (None, statuscode);
+        };
+    let request = Request::Fetch {
+        stacks: stacks
+            .as_bytes()
+            .chunks(STACK_LEN)
+            .map(|stack| String::from_utf8_lossy(stack).into_owned())
+            .collect(),
+        path: address.1.clone(),
     };
-    let mut payload = program_version[0].to_le_bytes().to_vec();
-    payload.extend_from_slice(&program_version[1].to_le_bytes());
-    payload.extend_from_slice(&program_version[2].to_le_bytes());
-    payload.extend_from_slice(stacks.as_bytes());
-    payload.extend_from_slice("/".as_bytes());
-    payload.extend_from_slice(address.1.as_bytes());
-    send_data(&payload, &stream);
-    let response = receive_data(&stream);
-    match response.len() {
-        4 => {
-            let code = u32::from_le_bytes(response.try_into().unwrap());
-            error!("{}", &status::decode(&code));
-            statuscode = code;
-        }
-        0..9 => {
-            error!("Server send an invalid response.");
-            return (None, status::BAD_RESPONSE);
+    let request = match request.encode() {
+        Ok(request) => request,
+        Err(e) => {
+            error!("Cannot ask {} with stacks {:?}: {}", &address.0, stacks, e);
+            return (None, Status::ShatTheBed);
         }
-        _ => {
-            let code = u32::from_le_bytes(response[0..4].try_into().unwrap());
-            match code {
-                status::SUCCESS => {
-                    let stack = String::from_utf8_lossy(&response[4..9]).to_string();
-                    info!("Server responsed with protocol {}", stack);
-                    return (Some((response[9..].to_vec(), stack)), code);
-                }
-                x => {
-                    error!("{}", &status::decode(&x));
-                    statuscode = x;
-                }
+    };
+    if let Err(e) = send_message_async(&request, &mut stream, compression).await {
+        error!("Failed to send request to {}: {}", &address.0, e);
+        return (None, Status::HostUnreachable);
+    }
+    let response =
+        match receive_message_async(&mut stream, &FrameLimits::default(), compression).await {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Failed to receive page from {}: {}", &address.0, e);
+                return (None, Status::BadResponse);
             }
+        };
+    match Response::decode(&response) {
+        Ok(Response::Page { stack, body }) => {
+            info!("Server responsed with protocol {}", stack);
+            (Some((body, stack)), Status::Success)
+        }
+        Ok(Response::Status(code)) => {
+            error!("{}", code);
+            (None, code)
+        }
+        Err(e) => {
+            error!("Server send an invalid response: {}", e);
+            (None, Status::BadResponse)
         }
     }
-    (None, statuscode)
 }
 
 #[derive(Debug)]
 enum PageContent {
-    Page((gtk::Box, u32)),
-    Status(u32),
+    Page((gtk::Box, Status)),
+    Status(Status),
     Failure(io::Error),
     Nothing,
     Paused,
@@ -1,74 +1,94 @@
+use async_std::{
+    net::{TcpListener, TcpStream},
+    stream::StreamExt,
+};
+use futures_rustls::TlsAcceptor;
 use std::{
-    cmp::Ordering,
     collections::HashMap,
     env,
     fs::File,
     io::Read,
-    net::{TcpListener, TcpStream},
     path::{Component, Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 use tracing::{error, info, trace, warn};
-use utils::{receive_data, send_data, send_error, status, trace_subscription, version_compare};
+use utils::{
+    Status,
+    compression::{
+        Compression, receive_message_async, send_error_message_async, send_message_async,
+    },
+    config::{Config, Opt, Spec},
+    framing::FrameLimits,
+    handshake::{Capabilities, server_handshake},
+    protocol::content::{Request, Response},
+    send_error_async,
+    tls::{Stream, accept, server_acceptor},
+    trace_subscription,
+};
 
-const DEFAULT_PORT: u16 = 6204;
+const SPEC: Spec = Spec {
+    program: "server",
+    about: "Serves pages from a directory to browsers.",
+    applet: "server",
+    env_prefix: "SERVER",
+    options: &[
+        Opt::value(
+            "directory",
+            Some('d'),
+            "DIR",
+            "Directory holding the site [default: current directory]",
+        ),
+        Opt::value("port", Some('p'), "PORT", "Port to listen on").default("6204"),
+        Opt::value(
+            "stacks",
+            Some('s'),
+            "FILE",
+            "Stacks file [default: DIR/stacks.txt]",
+        ),
+        Opt::value(
+            "tls-cert",
+            None,
+            "FILE",
+            "PEM certificate chain; enables TLS together with --tls-key",
+        ),
+        Opt::value("tls-key", None, "FILE", "PEM private key for --tls-cert"),
+        Opt::count("verbose", Some('v'), "Log more; repeat for trace output"),
+    ],
+};
+const REQUEST_LIMITS: FrameLimits = FrameLimits::new(64 * 1024, Some(Duration::from_secs(10)));
+/// Optional protocol features this service offers to clients.
+const CAPABILITIES: Capabilities = Compression::ALL;
 #[async_std::main]
 async fn main() {
-    let program_version: Vec<u32> = env!("CARGO_PKG_VERSION")
-        .split('.')
-        .map(|f| match f.parse::<u32>() {
-            Ok(version) => version,
-            Err(e) => {
-                panic!("Failed to parse version: {e}");
-            }
-        })
-        .collect();
-    assert!(program_version.len() > 1);
-    let mut verbose_level = 0u8;
-    let args: Vec<String> = env::args().collect();
-    let mut portstr = DEFAULT_PORT.to_string();
-    let mut pwd_str = String::new();
-    let mut stacksloc_str = String::new();
-    for (i, arg) in args.iter().enumerate() {
-        if arg.starts_with("--") {
-            match arg.strip_prefix("--").unwrap_or_default() {
-                "directory" => pwd_str = args[i + 1].clone(),
-                "port" => portstr = args[i + 1].clone(),
-                "stacks" => stacksloc_str = args[i + 1].clone(),
-                "verbose" => verbose_level += 1,
-                _ => panic!("Pre-init failure; unknown long-name argument: {arg}"),
-            }
-        } else if arg.starts_with("-") {
-            let mut argindex = i;
-            for char in arg.strip_prefix("-").unwrap_or_default().chars() {
-                match char {
-                    'd' => {
-                        pwd_str = args[argindex + 1].clone();
-                        argindex += 1;
-                    }
-                    'p' => {
-                        portstr = args[argindex + 1].clone();
-                        argindex += 1;
-                    }
-                    's' => {
-                        stacksloc_str = args[argindex + 1].clone();
-                        argindex += 1;
-                    }
-                    'v' => verbose_level += 1,
-                    _ => panic!("Pre-init failure; unknown short-name argument: {arg}"),
+    let config = Config::load_or_exit(&SPEC);
+    trace_subscription(config.count("verbose"));
+    let port: u16 = match config.require("port") {
+        Ok(port) => port,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    let pwd_str = config.get("directory").unwrap_or_default();
+    let stacksloc_str = config.get("stacks").unwrap_or_default();
+    let tls = match (config.get("tls-cert"), config.get("tls-key")) {
+        (None, None) => None,
+        (Some(tls_cert), Some(tls_key)) => {
+            match server_acceptor(Path::new(tls_cert), Path::new(tls_key)) {
+                Ok(acceptor) => {
+                    info!("TLS enabled using certificate {}", tls_cert);
+                    Some(acceptor)
+                }
+                Err(e) => {
+                    error!("Failed to set up TLS: {}", e);
+                    return;
                 }
             }
         }
-    }
-    trace_subscription(verbose_level);
-    let port = match portstr.parse() {
-        Ok(p) => p,
-        Err(e) => {
-            warn!(
-                "Failed to parse port: {}. Defaulting to {}",
-                e, DEFAULT_PORT
-            );
-            DEFAULT_PORT
+        _ => {
+            error!("TLS needs both --tls-cert and --tls-key");
+            return;
         }
     };
     let pwd = if pwd_str.is_empty() {
@@ -80,9 +100,9 @@ async fn main() {
             }
         }
     } else {
-        let directory = Path::new(&pwd_str);
+        let directory = Path::new(pwd_str);
         if !directory.is_dir() {
-            error!("Directory does not exist: {}", &pwd_str);
+            error!("Directory does not exist: {}", pwd_str);
             return;
         }
         directory.to_path_buf()
@@ -93,13 +113,13 @@ async fn main() {
         if dir.is_file() {
             dir
         } else {
-            error!("Cannot find stacks file: {}", &stacksloc_str);
+            error!("Cannot find stacks file: {}", stacksloc_str);
             return;
         }
     } else {
-        let file = Path::new(&stacksloc_str);
+        let file = Path::new(stacksloc_str);
         if !file.is_file() {
-            error!("Cannot find specified stacks file: {}", &stacksloc_str);
+            error!("Cannot find specified stacks file: {}", stacksloc_str);
             return;
         }
         file.to_path_buf()
@@ -119,11 +139,11 @@ async fn main() {
             stacks.insert(stack.to_string(), loc.to_string());
         }
     } else {
-        error!("Failed to read stacks file: {}", &stacksloc_str);
+        error!("Failed to read stacks file: {}", stacksloc_str);
         return;
     }
     let stacks = Arc::new(stacks);
-    let listener = match TcpListener::bind("0.0.0.0:".to_owned() + &port.to_string()) {
+    let listener = match TcpListener::bind("0.0.0.0:".to_owned() + &port.to_string()).await {
         Ok(listener) => listener,
         Err(e) => {
             error!("Port is unavailable: {}", e);
@@ -131,7 +151,8 @@ async fn main() {
         }
     };
     info!("Listening on port {}. Server setup OK!", port);
-    for stream in listener.incoming() {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
         match stream {
             Err(e) => {
                 warn!("Failed to accept connection: {}", e);
@@ -144,8 +165,9 @@ async fn main() {
                 );
                 let dir_clone = pwd.clone();
                 let stacks_ptr = Arc::clone(&stacks);
+                let tls = tls.clone();
                 async_std::task::spawn(async move {
-                    handle_connection(stream, &dir_clone, &stacks_ptr).await;
+                    handle_connection(stream, tls.as_ref(), &dir_clone, &stacks_ptr).await;
                 });
             }
         }
@@ -154,96 +176,96 @@ async fn main() {
 
 async fn handle_connection(
     stream: TcpStream,
+    tls: Option<&TlsAcceptor>,
     directory: &Path,
     stacks: &Arc<HashMap<String, String>>,
 ) {
-    let program_version: Vec<u32> = env!("CARGO_PKG_VERSION")
-        .split('.')
-        .map(|f| match f.parse::<u32>() {
-            Ok(version) => version,
-            Err(e) => {
-                panic!("Failed to parse version: {e}");
-            }
-        })
-        .collect();
-    assert!(program_version.len() > 2);
     let peer = match stream.peer_addr() {
         Ok(peer) => peer,
         Err(e) => {
             warn!("Some fuckn' loser decided to not have an IP address: {}", e);
-            send_error(&stream, status::BAD_REQUEST);
+            send_error_async(&stream, Status::BadRequest).await;
             return;
         }
     };
-    let data = receive_data(&stream);
-    if data.len() < 14 {
-        warn!("Payload from {}:{} was too short.", peer.ip(), peer.port());
-        send_error(&stream, status::TOO_SMALL);
-        return;
-    }
-    let client_maj = u32::from_le_bytes(data[0..4].try_into().unwrap_or([0, 0, 0, 0]));
-    let client_min = u32::from_le_bytes(data[4..8].try_into().unwrap_or([0, 0, 0, 0]));
-    let client_tiny = u32::from_le_bytes(data[8..12].try_into().unwrap_or([0, 0, 0, 0]));
-    match version_compare((client_maj, client_min, client_tiny), peer, program_version) {
-        Ordering::Greater => send_error(&stream, status::DOWNGRADE_REQUIRED),
-        Ordering::Less => send_error(&stream, status::UPGRADE_REQUIRED),
-        _ => (),
-    }
-    let mut data = &data[12..];
-    if data.is_empty() {
-        trace!("Payload from {}:{} was too short.", peer.ip(), peer.port());
-        send_error(&stream, status::TOO_SMALL);
-        return;
-    }
-    let mut client_protocols = vec![[0u8; 5]];
-    loop {
-        if data.len() < 5 {
-            trace!("Payload from {}:{} was too short.", peer.ip(), peer.port());
-            send_error(&stream, status::TOO_SMALL);
+    let mut stream = match accept(stream, tls, &REQUEST_LIMITS).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!(
+                "TLS handshake with {}:{} failed: {}",
+                peer.ip(),
+                peer.port(),
+                e
+            );
             return;
         }
-        client_protocols.push(data[0..5].try_into().unwrap_or([0u8; 5]));
-        data = &data[5..];
-        if data.is_empty() {
-            trace!("Unrecognised request from {}:{}", peer.ip(), peer.port());
-            send_error(&stream, status::UNPROCESSABLE);
+    };
+    let compression = match server_handshake(&mut stream, peer, CAPABILITIES, &REQUEST_LIMITS).await
+    {
+        Ok(session) => session.compression(),
+        Err(e) => {
+            warn!("Handshake with {}:{} failed: {}", peer.ip(), peer.port(), e);
             return;
         }
-        if data[0] == b'/' {
-            data = data.get(1..).unwrap_or_default();
-            break;
+    };
+    let data = match receive_message_async(&mut stream, &REQUEST_LIMITS, compression).await {
+        Ok(data) => data,
+        Err(e) => {
+            warn!(
+                "Failed to receive request from {}:{}: {}",
+                peer.ip(),
+                peer.port(),
+                e
+            );
+            if let Some(code) = e.status() {
+                send_error_message_async(&mut stream, code, compression).await;
+            }
+            return;
         }
-    }
+    };
+    let request = match Request::decode(&data) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Bad request from {}:{}: {}", peer.ip(), peer.port(), e);
+            send_error_message_async(&mut stream, e.status(), compression).await;
+            return;
+        }
+    };
+    let Request::Fetch {
+        stacks: client_stacks,
+        path,
+    } = request;
     let mut using_protocol = None;
     let stacks = Arc::clone(stacks);
-    for protocol in client_protocols {
-        let stack = String::from_utf8_lossy(&protocol).to_string();
+    for stack in client_stacks {
         if let Some(protocol) = stacks.get(&stack) {
             using_protocol = Some((stack, protocol.trim().to_string()));
             break;
         }
     }
-    let location = String::from_utf8_lossy(&data[0..]);
     match using_protocol {
-        None => send_error(&stream, status::UNPROCESSABLE),
-        Some(protocol) => get_content(&stream, protocol, directory, &location),
+        None => send_error_message_async(&mut stream, Status::Unprocessable, compression).await,
+        Some(protocol) => get_content(&mut stream, compression, protocol, directory, &path).await,
     }
 }
 
-fn get_content(
-    stream: &TcpStream,
+async fn get_content(
+    stream: &mut Stream,
+    compression: Compression,
     protocol: (String, String),
     directory: &Path,
     destination: &str,
 ) {
     let (stack, protocol) = protocol;
-    let mut payload = status::SUCCESS.to_le_bytes().to_vec();
-    payload.extend_from_slice(stack.as_bytes());
+    // Pages are plain files, so a query cannot change which one is served.
+    let destination = destination
+        .split_once('?')
+        .map_or(destination, |(path, _)| path);
     let file = if protocol.starts_with("/") {
         match get_file(&protocol, directory) {
             Some(content) => content,
             None => {
-                send_error(stream, status::SHAT_THE_BED);
+                send_error_message_async(stream, Status::ShatTheBed, compression).await;
                 return;
             }
         }
@@ -252,31 +274,26 @@ fn get_content(
         match get_file(&dest, directory) {
             Some(content) => content,
             None => {
-                send_error(stream, status::NOT_FOUND);
+                send_error_message_async(stream, Status::NotFound, compression).await;
                 return;
             }
         }
     };
-    let filedump = File::open(&file);
-    match filedump {
-        Ok(mut file) => {
-            let mut buffer = Vec::new();
-            match file.read_to_end(&mut buffer) {
-                Ok(_) => {
-                    payload.extend_from_slice(&buffer);
-                }
-                Err(e) => {
-                    warn!("Failed to read file: {}", e);
-                    send_error(stream, status::NOT_FOUND)
-                }
+    match async_std::fs::read(&file).await {
+        Ok(buffer) => {
+            let response = Response::Page {
+                stack,
+                body: buffer,
+            };
+            if let Err(e) = send_message_async(&response.encode(), stream, compression).await {
+                warn!("Failed to send page: {}", e);
             }
         }
         Err(e) => {
-            warn!("Failed to open file: {}", e);
-            send_error(stream, status::NOT_FOUND)
+            warn!("Failed to read file: {}", e);
+            send_error_message_async(stream, Status::NotFound, compression).await
         }
     }
-    send_data(&payload, stream);
 }
 
 fn get_file(subpath: &str, directory: &Path) -> Option<PathBuf> {
@@ -0,0 +1,364 @@
+//! Pre-shared keys for managing a DNS provider's records.
+//!
+//! The provider loads a key file with one key per line: an id, the secret as
+//! hex, and optionally the names the key may manage. A key without names may
+//! manage every record, including `.`. Blank lines and `#` comments are
+//! skipped, e.g.:
+//!
+//! ```text
+//! # id       secret (hex, at least 16 bytes)    names
+//! admin      6b1f0c9e2d7a4f3b8e5c1a0d9f2b7e46
+//! registrar  0e4d7c2b9a8f1e3d5c6b7a8f9e0d1c2b  example shop
+//! ```
+//!
+//! Clients sign each request with the same secret. The provider rejects
+//! requests whose timestamp is more than `MAX_SKEW` away from its clock, and
+//! any nonce it has already seen within that window.
+
+use crate::{
+    Status,
+    protocol::{
+        EncodeError,
+        admin::{Command, NONCE_LEN, Signed},
+    },
+};
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How far, in seconds, a request's timestamp may be from the provider's clock.
+pub const MAX_SKEW: u64 = 300;
+/// Shortest secret accepted, in bytes.
+pub const MIN_SECRET_LEN: usize = 16;
+
+#[derive(Debug)]
+pub enum KeyError {
+    Io(PathBuf, io::Error),
+    Parse { line: usize, reason: String },
+    Random,
+    Encode(EncodeError),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Io(path, e) => write!(f, "failed to read {}: {e}", path.display()),
+            KeyError::Parse { line, reason } => write!(f, "line {line}: {reason}"),
+            KeyError::Random => write!(f, "no randomness available for a nonce"),
+            KeyError::Encode(e) => write!(f, "cannot send command: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KeyError::Io(_, e) => Some(e),
+            KeyError::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+pub struct Key {
+    pub id: String,
+    secret: hmac::Key,
+    /// `None` when the key may manage every name.
+    names: Option<Vec<String>>,
+}
+
+impl Key {
+    /// A key from its hex `secret`, e.g. as given to the admin CLI.
+    pub fn from_hex(id: &str, secret: &str, names: Option<Vec<String>>) -> Result<Self, String> {
+        let secret = decode_hex(secret)?;
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(format!(
+                "secret is {} bytes, expected at least {MIN_SECRET_LEN}",
+                secret.len()
+            ));
+        }
+        Ok(Key {
+            id: id.to_owned(),
+            secret: hmac::Key::new(hmac::HMAC_SHA256, &secret),
+            names,
+        })
+    }
+
+    pub fn may_manage(&self, name: &str) -> bool {
+        self.names
+            .as_ref()
+            .is_none_or(|names| names.iter().any(|allowed| allowed == name))
+    }
+
+    /// Whether the key may manage every name, as a zone transfer needs.
+    pub fn is_unrestricted(&self) -> bool {
+        self.names.is_none()
+    }
+
+    /// Wraps `command` for sending, stamped with the current time.
+    pub fn sign(&self, command: Command) -> Result<Signed, KeyError> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| KeyError::Random)?;
+        let mut signed = Signed {
+            key_id: self.id.clone(),
+            timestamp: now(),
+            nonce,
+            command,
+            mac: Vec::new(),
+        };
+        let body = signed.body().map_err(KeyError::Encode)?;
+        signed.mac = hmac::sign(&self.secret, &body).as_ref().to_vec();
+        Ok(signed)
+    }
+
+    fn verify(&self, signed: &Signed) -> bool {
+        signed
+            .body()
+            .is_ok_and(|body| hmac::verify(&self.secret, &body, &signed.mac).is_ok())
+    }
+}
+
+/// The keys a provider accepts, and the nonces it has seen recently.
+pub struct KeyRing {
+    keys: HashMap<String, Key>,
+    seen: Mutex<HashMap<[u8; NONCE_LEN], u64>>,
+}
+
+impl KeyRing {
+    pub fn load(path: &Path) -> Result<Self, KeyError> {
+        let text = fs::read_to_string(path).map_err(|e| KeyError::Io(path.to_owned(), e))?;
+        Self::parse(&text)
+    }
+
+    /// Keys from the text of a key file.
+    fn parse(text: &str) -> Result<Self, KeyError> {
+        let mut keys = HashMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let parse_error = |reason: String| KeyError::Parse {
+                line: index + 1,
+                reason,
+            };
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(id) = fields.next() else {
+                continue;
+            };
+            let Some(secret) = fields.next() else {
+                return Err(parse_error(format!("key {id:?} has no secret")));
+            };
+            let names: Vec<String> = fields.map(str::to_owned).collect();
+            let key = Key::from_hex(id, secret, (!names.is_empty()).then_some(names))
+                .map_err(parse_error)?;
+            if keys.insert(id.to_owned(), key).is_some() {
+                return Err(parse_error(format!("key {id:?} is defined twice")));
+            }
+        }
+        Ok(KeyRing {
+            keys,
+            seen: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The key that signed `signed`, if the signature holds and the request
+    /// is fresh. Fails with `Unauthorized` otherwise.
+    pub fn authenticate(&self, signed: &Signed) -> Result<&Key, Status> {
+        let key = self
+            .keys
+            .get(&signed.key_id)
+            .filter(|key| key.verify(signed))
+            .ok_or(Status::Unauthorized)?;
+        let now = now();
+        if signed.timestamp.abs_diff(now) > MAX_SKEW {
+            return Err(Status::Unauthorized);
+        }
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        seen.retain(|_, timestamp| timestamp.abs_diff(now) <= MAX_SKEW);
+        if seen.insert(signed.nonce, signed.timestamp).is_some() {
+            return Err(Status::Unauthorized);
+        }
+        Ok(key)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("secret has an odd number of hex digits".to_owned());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|at| {
+            // `from_str_radix` alone would also take a sign, as in `+f`.
+            hex.get(at..at + 2)
+                .filter(|pair| pair.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| "secret is not hex".to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    const SECRET: &str = "6b1f0c9e2d7a4f3b8e5c1a0d9f2b7e46";
+
+    fn ring() -> KeyRing {
+        KeyRing::parse(&format!(
+            "# comment\n\nadmin {SECRET}\nregistrar {SECRET}ff example shop\n"
+        ))
+        .unwrap()
+    }
+
+    /// Signs `signed` again after its fields were changed.
+    fn resign(key: &Key, mut signed: Signed) -> Signed {
+        signed.mac = hmac::sign(&key.secret, &signed.body().unwrap())
+            .as_ref()
+            .to_vec();
+        signed
+    }
+
+    fn admin() -> Key {
+        Key::from_hex("admin", SECRET, None).unwrap()
+    }
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(decode_hex("00ff7A"), Ok(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode_hex(""), Ok(vec![]));
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+        assert!(decode_hex("+1").is_err());
+        assert!(decode_hex("éé").is_err());
+        assert!(Key::from_hex("short", "00ff", None).is_err());
+    }
+
+    #[test]
+    fn parses_key_files() {
+        let ring = ring();
+        assert_eq!(ring.len(), 2);
+        let registrar = &ring.keys["registrar"];
+        assert!(registrar.may_manage("shop"));
+        assert!(!registrar.may_manage("."));
+        assert!(!registrar.is_unrestricted());
+        assert!(ring.keys["admin"].is_unrestricted());
+        assert!(matches!(
+            KeyRing::parse(&format!("admin {SECRET}\nadmin {SECRET}\n")),
+            Err(KeyError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            KeyRing::parse("\nadmin\n"),
+            Err(KeyError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn reads_key_files() {
+        let path = env::temp_dir().join(format!("auth-test-{}-read.keys", process::id()));
+        fs::write(&path, format!("admin {SECRET}\n")).unwrap();
+        let ring = KeyRing::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ring.unwrap().len(), 1);
+        assert!(matches!(KeyRing::load(&path), Err(KeyError::Io(..))));
+    }
+
+    #[test]
+    fn verifies_macs() {
+        let ring = ring();
+        let signed = admin().sign(Command::List).unwrap();
+        assert!(ring.authenticate(&signed).is_ok());
+        let tampered = Signed {
+            command: Command::Delete {
+                name: "shop".to_owned(),
+            },
+            ..admin().sign(Command::List).unwrap()
+        };
+        assert_eq!(
+            ring.authenticate(&tampered).err(),
+            Some(Status::Unauthorized)
+        );
+        let other_secret = Key::from_hex("admin", &SECRET.replace('6', "7"), None).unwrap();
+        let forged = other_secret.sign(Command::List).unwrap();
+        assert_eq!(ring.authenticate(&forged).err(), Some(Status::Unauthorized));
+        let unknown = Key::from_hex("nobody", SECRET, None).unwrap();
+        let unknown = unknown.sign(Command::List).unwrap();
+        assert_eq!(
+            ring.authenticate(&unknown).err(),
+            Some(Status::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn rejects_replayed_nonces() {
+        let ring = ring();
+        let signed = admin().sign(Command::List).unwrap();
+        assert!(ring.authenticate(&signed).is_ok());
+        assert_eq!(ring.authenticate(&signed).err(), Some(Status::Unauthorized));
+        // The same nonce under a new timestamp is still a replay.
+        let later = resign(
+            &admin(),
+            Signed {
+                timestamp: signed.timestamp + 1,
+                ..signed
+            },
+        );
+        assert_eq!(ring.authenticate(&later).err(), Some(Status::Unauthorized));
+    }
+
+    #[test]
+    fn rejects_skewed_timestamps() {
+        let ring = ring();
+        let at = |timestamp| {
+            resign(
+                &admin(),
+                Signed {
+                    timestamp,
+                    ..admin().sign(Command::List).unwrap()
+                },
+            )
+        };
+        let now = now();
+        assert!(ring.authenticate(&at(now - MAX_SKEW + 10)).is_ok());
+        assert!(ring.authenticate(&at(now + MAX_SKEW - 10)).is_ok());
+        assert_eq!(
+            ring.authenticate(&at(now - MAX_SKEW - 10)).err(),
+            Some(Status::Unauthorized)
+        );
+        assert_eq!(
+            ring.authenticate(&at(now + MAX_SKEW + 10)).err(),
+            Some(Status::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn refuses_to_sign_oversize_commands() {
+        let command = Command::Get {
+            name: "x".repeat(300),
+        };
+        assert!(matches!(admin().sign(command), Err(KeyError::Encode(_))));
+    }
+}
@@ -0,0 +1,172 @@
+//! Negotiated compression of whole messages, layered above the framing.
+//!
+//! Clients advertise the algorithms they support as capabilities and the
+//! server keeps the one it prefers. On a session with an algorithm, every
+//! message starts with one byte naming how the rest is encoded: `0` for stored
+//! as-is, otherwise the algorithm's id. Short payloads and payloads that do not
+//! shrink are stored. Sessions without compression send messages unchanged.
+
+use crate::{
+    Status,
+    framing::{FrameError, FrameLimits, receive_data_async, send_data_async},
+    handshake::Capabilities,
+};
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use std::io::{self, Read, Write};
+use tracing::{debug, trace};
+
+/// Payloads shorter than this are never worth compressing.
+const MIN_COMPRESS_LEN: usize = 128;
+const STORED: u8 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+    Zstd,
+}
+
+impl Compression {
+    /// Algorithms this build supports, most preferred first.
+    pub const SUPPORTED: [Compression; 2] = [Compression::Zstd, Compression::Deflate];
+    /// Capabilities covering every supported algorithm.
+    pub const ALL: Capabilities =
+        Capabilities::from_bits(Capabilities::DEFLATE.bits() | Capabilities::ZSTD.bits());
+
+    /// Most preferred algorithm within `capabilities`.
+    pub fn negotiate(capabilities: Capabilities) -> Self {
+        Self::SUPPORTED
+            .into_iter()
+            .find(|compression| capabilities.contains(compression.capability()))
+            .unwrap_or(Compression::None)
+    }
+
+    pub fn capability(self) -> Capabilities {
+        match self {
+            Compression::None => Capabilities::NONE,
+            Compression::Deflate => Capabilities::DEFLATE,
+            Compression::Zstd => Capabilities::ZSTD,
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Compression::None => STORED,
+            Compression::Deflate => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    /// Encodes `payload` for a session using this algorithm.
+    pub fn encode(self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        if self == Compression::None {
+            return Ok(payload.to_vec());
+        }
+        if payload.len() >= MIN_COMPRESS_LEN {
+            let compressed = match self {
+                Compression::None => unreachable!(),
+                Compression::Deflate => {
+                    let mut encoder = flate2::write::DeflateEncoder::new(
+                        vec![self.id()],
+                        flate2::Compression::default(),
+                    );
+                    encoder.write_all(payload)?;
+                    encoder.finish()?
+                }
+                Compression::Zstd => {
+                    let mut compressed = vec![self.id()];
+                    zstd::stream::copy_encode(payload, &mut compressed, 0)?;
+                    compressed
+                }
+            };
+            if compressed.len() <= payload.len() {
+                trace!(
+                    "Compressed {} bytes to {} with {:?}",
+                    payload.len(),
+                    compressed.len() - 1,
+                    self
+                );
+                return Ok(compressed);
+            }
+        }
+        let mut stored = Vec::with_capacity(payload.len() + 1);
+        stored.push(STORED);
+        stored.extend_from_slice(payload);
+        Ok(stored)
+    }
+
+    /// Reverses `encode`, refusing to inflate past `limit` bytes.
+    pub fn decode(self, data: &[u8], limit: usize) -> Result<Vec<u8>, FrameError> {
+        if self == Compression::None {
+            return Ok(data.to_vec());
+        }
+        let Some((&method, body)) = data.split_first() else {
+            return Err(FrameError::Corrupt(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "missing compression method",
+            )));
+        };
+        match method {
+            STORED => Ok(body.to_vec()),
+            method if method == self.id() => match self {
+                Compression::None => unreachable!(),
+                Compression::Deflate => inflate(flate2::read::DeflateDecoder::new(body), limit),
+                Compression::Zstd => {
+                    let decoder =
+                        zstd::stream::read::Decoder::new(body).map_err(FrameError::Corrupt)?;
+                    inflate(decoder, limit)
+                }
+            },
+            method => Err(FrameError::Corrupt(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected compression method {method}"),
+            ))),
+        }
+    }
+}
+
+fn inflate(reader: impl Read, limit: usize) -> Result<Vec<u8>, FrameError> {
+    let mut data = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(FrameError::Corrupt)?;
+    if data.len() > limit {
+        debug!("Refusing to decompress past {} bytes.", limit);
+        return Err(FrameError::TooLarge { limit });
+    }
+    Ok(data)
+}
+
+/// `send_data_async` for a session that may have negotiated compression.
+pub async fn send_message_async<S: AsyncWrite + Unpin>(
+    payload: &[u8],
+    stream: S,
+    compression: Compression,
+) -> Result<(), FrameError> {
+    send_data_async(&compression.encode(payload)?, stream).await
+}
+
+/// `receive_data_async` for a session that may have negotiated compression.
+/// `limits.max_size` applies both before and after decompression.
+pub async fn receive_message_async<S: AsyncRead + Unpin>(
+    stream: S,
+    limits: &FrameLimits,
+    compression: Compression,
+) -> Result<Vec<u8>, FrameError> {
+    let data = receive_data_async(stream, limits).await?;
+    compression.decode(&data, limits.max_size)
+}
+
+/// `send_error_async` for a session that may have negotiated compression.
+pub async fn send_error_message_async<S: AsyncWrite + Unpin>(
+    mut stream: S,
+    err: Status,
+    compression: Compression,
+) {
+    if let Err(e) = send_message_async(&err.code().to_le_bytes(), &mut stream, compression).await {
+        debug!("Failed to send error {}: {}", err, e);
+    }
+    stream.close().await.unwrap_or_default();
+}
@@ -0,0 +1,741 @@
+//! Shared configuration for every binary.
+//!
+//! Each program describes its options once in a `Spec`. Values are then taken
+//! from, in increasing order of precedence: the option's default, a TOML
+//! config file, environment variables and the command line. The config file is
+//! `--config <FILE>`, else `<PREFIX>_CONFIG`, else `<program>.toml` in the
+//! applet's config directory if it exists. Keys in the file are the long
+//! option names, and the environment variable for `--sql-url` is
+//! `<PREFIX>_SQL_URL`, so secrets never have to appear on the command line.
+
+use crate::get_config_dir;
+use std::{
+    collections::HashMap,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Present or not, e.g. `--overwrite`.
+    Flag,
+    /// May be repeated to raise a level, e.g. `-vv`.
+    Count,
+    /// Takes a value; the last one given wins.
+    Value(&'static str),
+    /// Takes a value and may be repeated, keeping every value.
+    List(&'static str),
+    /// A `List` that also collects the bare arguments on the command line,
+    /// e.g. the names given to `webdig`.
+    Operands(&'static str),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Opt {
+    pub long: &'static str,
+    pub short: Option<char>,
+    pub kind: Kind,
+    pub help: &'static str,
+    pub default: Option<&'static str>,
+}
+
+impl Opt {
+    pub const fn flag(long: &'static str, short: Option<char>, help: &'static str) -> Self {
+        Self::new(long, short, Kind::Flag, help)
+    }
+
+    pub const fn count(long: &'static str, short: Option<char>, help: &'static str) -> Self {
+        Self::new(long, short, Kind::Count, help)
+    }
+
+    pub const fn value(
+        long: &'static str,
+        short: Option<char>,
+        metavar: &'static str,
+        help: &'static str,
+    ) -> Self {
+        Self::new(long, short, Kind::Value(metavar), help)
+    }
+
+    pub const fn list(
+        long: &'static str,
+        short: Option<char>,
+        metavar: &'static str,
+        help: &'static str,
+    ) -> Self {
+        Self::new(long, short, Kind::List(metavar), help)
+    }
+
+    pub const fn operands(long: &'static str, metavar: &'static str, help: &'static str) -> Self {
+        Self::new(long, None, Kind::Operands(metavar), help)
+    }
+
+    const fn new(long: &'static str, short: Option<char>, kind: Kind, help: &'static str) -> Self {
+        Self {
+            long,
+            short,
+            kind,
+            help,
+            default: None,
+        }
+    }
+
+    pub const fn default(mut self, value: &'static str) -> Self {
+        self.default = Some(value);
+        self
+    }
+
+    fn takes_value(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::Value(_) | Kind::List(_) | Kind::Operands(_)
+        )
+    }
+}
+
+const CONFIG: Opt = Opt::value("config", None, "FILE", "Read options from a TOML file");
+const HELP: Opt = Opt::flag("help", Some('h'), "Print this help and exit");
+
+/// Everything a program accepts.
+#[derive(Debug, Clone, Copy)]
+pub struct Spec {
+    pub program: &'static str,
+    pub about: &'static str,
+    /// Name passed to `get_config_dir` when looking for the default file.
+    pub applet: &'static str,
+    /// Prefix of environment variables, e.g. `DNS_PROVIDER`.
+    pub env_prefix: &'static str,
+    pub options: &'static [Opt],
+}
+
+impl Spec {
+    fn all_options(&self) -> impl Iterator<Item = &Opt> {
+        self.options.iter().chain([&CONFIG, &HELP])
+    }
+
+    fn by_long(&self, long: &str) -> Option<&Opt> {
+        self.all_options().find(|opt| opt.long == long)
+    }
+
+    fn by_short(&self, short: char) -> Option<&Opt> {
+        self.all_options().find(|opt| opt.short == Some(short))
+    }
+
+    fn operands(&self) -> Option<&Opt> {
+        self.options
+            .iter()
+            .find(|opt| matches!(opt.kind, Kind::Operands(_)))
+    }
+
+    fn env_var(&self, opt: &Opt) -> String {
+        format!(
+            "{}_{}",
+            self.env_prefix,
+            opt.long.to_ascii_uppercase().replace('-', "_")
+        )
+    }
+
+    pub fn usage(&self) -> String {
+        let operands = match self.operands().map(|opt| opt.kind) {
+            Some(Kind::Operands(metavar)) => format!(" [{metavar}]..."),
+            _ => String::new(),
+        };
+        let mut usage = format!(
+            "Usage: {} [OPTIONS]{}\n\n{}\n\nOptions:\n",
+            self.program, operands, self.about
+        );
+        let columns: Vec<_> = self
+            .all_options()
+            .map(|opt| {
+                let short = opt.short.map_or("    ".to_owned(), |c| format!("-{c}, "));
+                let value = match opt.kind {
+                    Kind::Value(metavar) | Kind::List(metavar) | Kind::Operands(metavar) => {
+                        format!(" <{metavar}>")
+                    }
+                    Kind::Flag | Kind::Count => String::new(),
+                };
+                (format!("{short}--{}{value}", opt.long), opt)
+            })
+            .collect();
+        let width = columns
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, opt) in columns {
+            usage += &format!("  {name:width$}  {}", opt.help);
+            if let Some(default) = opt.default {
+                usage += &format!(" [default: {default}]");
+            }
+            usage.push('\n');
+        }
+        usage += &format!(
+            "\nOptions can also be set as `name = value` in the config file, or through\n\
+             environment variables such as {}.\n",
+            self.env_var(&self.options.first().copied().unwrap_or(CONFIG))
+        );
+        usage
+    }
+}
+
+/// Where a value came from, for error messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Env(var) => write!(f, "${var}"),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// `--help` was given; carries the text to print.
+    Help(String),
+    UnknownOption(String),
+    MissingValue(String),
+    /// A value was given to an option that does not take one.
+    UnexpectedValue(String),
+    UnexpectedArgument(String),
+    File {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Toml {
+        path: PathBuf,
+        message: String,
+    },
+    Invalid {
+        option: &'static str,
+        value: String,
+        source: Source,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Help(usage) => write!(f, "{usage}"),
+            ConfigError::UnknownOption(opt) => write!(f, "unknown option {opt}"),
+            ConfigError::MissingValue(opt) => write!(f, "option {opt} needs a value"),
+            ConfigError::UnexpectedValue(opt) => write!(f, "option {opt} does not take a value"),
+            ConfigError::UnexpectedArgument(arg) => write!(f, "unexpected argument {arg:?}"),
+            ConfigError::File { path, source } => {
+                write!(f, "cannot read config file {}: {}", path.display(), source)
+            }
+            ConfigError::Toml { path, message } => {
+                write!(f, "invalid config file {}: {}", path.display(), message)
+            }
+            ConfigError::Invalid {
+                option,
+                value,
+                source,
+                reason,
+            } => write!(
+                f,
+                "invalid value {value:?} for --{option} (from {source}): {reason}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::File { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    values: Vec<String>,
+    source: Source,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    spec: Spec,
+    entries: HashMap<&'static str, Entry>,
+}
+
+impl Config {
+    /// Loads the configuration of the running process.
+    pub fn load(spec: &Spec) -> Result<Self, ConfigError> {
+        Self::from_sources(spec, env::args().skip(1), |var| env::var(var).ok())
+    }
+
+    /// `load`, printing help or errors and exiting when there is nothing to
+    /// run. Called before logging is set up, so it writes to the terminal.
+    pub fn load_or_exit(spec: &Spec) -> Self {
+        match Self::load(spec) {
+            Ok(config) => config,
+            Err(ConfigError::Help(usage)) => {
+                print!("{usage}");
+                process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", spec.program, e);
+                eprintln!("Try '{} --help' for more information.", spec.program);
+                process::exit(2);
+            }
+        }
+    }
+
+    pub fn from_sources(
+        spec: &Spec,
+        args: impl IntoIterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let cli = parse_args(spec, args)?;
+        let mut config = Self {
+            spec: *spec,
+            entries: HashMap::new(),
+        };
+        let file = match cli.get(CONFIG.long) {
+            Some(entry) => entry.values.last().map(PathBuf::from),
+            None => env(&spec.env_var(&CONFIG)).map(PathBuf::from),
+        };
+        match file {
+            Some(path) => config.apply_file(&path)?,
+            None => {
+                let default = get_config_dir(spec.applet)
+                    .map(|dir| dir.join(format!("{}.toml", spec.program)));
+                if let Some(path) = default.filter(|path| path.is_file()) {
+                    config.apply_file(&path)?;
+                }
+            }
+        }
+        for opt in spec.options {
+            let var = spec.env_var(opt);
+            if let Some(value) = env(&var) {
+                let source = Source::Env(var);
+                let values = match opt.kind {
+                    Kind::List(_) | Kind::Operands(_) => {
+                        value.split(',').map(str::to_owned).collect()
+                    }
+                    _ => vec![value],
+                };
+                config.set(opt, values, source)?;
+            }
+        }
+        for (long, entry) in cli {
+            let opt = *spec.by_long(long).expect("parsed options exist");
+            config.set(&opt, entry.values, Source::Cli)?;
+        }
+        Ok(config)
+    }
+
+    fn apply_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::File {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let table: toml::Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| ConfigError::Toml {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            })?;
+        let toml_error = |message: String| ConfigError::Toml {
+            path: path.to_path_buf(),
+            message,
+        };
+        for (key, value) in table {
+            let opt = match self.spec.options.iter().find(|opt| opt.long == key) {
+                Some(opt) => *opt,
+                None => return Err(toml_error(format!("unknown key {key:?}"))),
+            };
+            let scalar = |value: toml::Value| match value {
+                toml::Value::String(s) => Ok(s),
+                toml::Value::Integer(i) => Ok(i.to_string()),
+                toml::Value::Float(x) => Ok(x.to_string()),
+                toml::Value::Boolean(b) => Ok(b.to_string()),
+                other => Err(toml_error(format!(
+                    "key {key:?} must be a single value, found {}",
+                    other.type_str()
+                ))),
+            };
+            let values = match (opt.kind, value) {
+                (Kind::List(_) | Kind::Operands(_), toml::Value::Array(items)) => {
+                    items.into_iter().map(scalar).collect::<Result<_, _>>()?
+                }
+                (_, value) => vec![scalar(value)?],
+            };
+            self.set(&opt, values, Source::File(path.to_path_buf()))?;
+        }
+        Ok(())
+    }
+
+    /// Replaces whatever a lower-precedence source set for `opt`.
+    fn set(&mut self, opt: &Opt, values: Vec<String>, source: Source) -> Result<(), ConfigError> {
+        let invalid = |value: &str, reason: &str| ConfigError::Invalid {
+            option: opt.long,
+            value: value.to_owned(),
+            source: source.clone(),
+            reason: reason.to_owned(),
+        };
+        let values = match opt.kind {
+            Kind::Flag => {
+                let value = values.last().map(String::as_str).unwrap_or("true");
+                vec![
+                    parse_bool(value)
+                        .ok_or_else(|| invalid(value, "expected true or false"))?
+                        .to_string(),
+                ]
+            }
+            Kind::Count => {
+                let value = values.last().map(String::as_str).unwrap_or("0");
+                let level = match parse_bool(value) {
+                    Some(set) => u8::from(set),
+                    None => value
+                        .parse::<u8>()
+                        .map_err(|_| invalid(value, "expected a number"))?,
+                };
+                vec![level.to_string()]
+            }
+            Kind::Value(_) => values.into_iter().last().into_iter().collect(),
+            Kind::List(_) | Kind::Operands(_) => values,
+        };
+        self.entries.insert(opt.long, Entry { values, source });
+        Ok(())
+    }
+
+    fn opt(&self, long: &str) -> &Opt {
+        self.spec
+            .by_long(long)
+            .unwrap_or_else(|| panic!("option --{long} is not in the spec"))
+    }
+
+    /// The value of a `Value` option, falling back to its default.
+    pub fn get(&self, long: &str) -> Option<&str> {
+        match self.entries.get(long) {
+            Some(entry) => entry.values.last().map(String::as_str),
+            None => self.opt(long).default,
+        }
+    }
+
+    /// Parses the value of `long`, naming where it came from if it is invalid.
+    pub fn parse<T>(&self, long: &str) -> Result<Option<T>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let Some(value) = self.get(long) else {
+            return Ok(None);
+        };
+        value
+            .parse()
+            .map(Some)
+            .map_err(|e: T::Err| ConfigError::Invalid {
+                option: self.opt(long).long,
+                value: value.to_owned(),
+                source: self
+                    .entries
+                    .get(long)
+                    .map_or(Source::Default, |entry| entry.source.clone()),
+                reason: e.to_string(),
+            })
+    }
+
+    /// `parse` for options that always have a value, such as those with a
+    /// default.
+    pub fn require<T>(&self, long: &str) -> Result<T, ConfigError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.parse(long)?
+            .ok_or_else(|| ConfigError::MissingValue(format!("--{long}")))
+    }
+
+    pub fn flag(&self, long: &str) -> bool {
+        self.get(long) == Some("true")
+    }
+
+    pub fn count(&self, long: &str) -> u8 {
+        self.get(long)
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(0)
+    }
+
+    pub fn list(&self, long: &str) -> Vec<&str> {
+        match self.entries.get(long) {
+            Some(entry) => entry.values.iter().map(String::as_str).collect(),
+            None => self.opt(long).default.into_iter().collect(),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Collects command line options; a repeated `Count` becomes its level.
+fn parse_args(
+    spec: &Spec,
+    args: impl IntoIterator<Item = String>,
+) -> Result<HashMap<&'static str, Entry>, ConfigError> {
+    let mut found: HashMap<&'static str, Entry> = HashMap::new();
+    let mut add = |opt: &Opt, value: Option<String>| -> Result<(), ConfigError> {
+        if opt.long == HELP.long {
+            return Err(ConfigError::Help(spec.usage()));
+        }
+        let entry = found.entry(opt.long).or_insert(Entry {
+            values: Vec::new(),
+            source: Source::Cli,
+        });
+        match opt.kind {
+            Kind::Count => {
+                let level = entry
+                    .values
+                    .first()
+                    .and_then(|level| level.parse::<u8>().ok())
+                    .unwrap_or(0);
+                entry.values = vec![level.saturating_add(1).to_string()];
+            }
+            _ => entry.values.extend(value),
+        }
+        Ok(())
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (long, None),
+            };
+            let opt = spec
+                .by_long(name)
+                .ok_or_else(|| ConfigError::UnknownOption(format!("--{name}")))?;
+            let value = match (opt.takes_value(), inline) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(
+                    args.next()
+                        .ok_or_else(|| ConfigError::MissingValue(format!("--{name}")))?,
+                ),
+                (false, Some(_)) => return Err(ConfigError::UnexpectedValue(format!("--{name}"))),
+                (false, None) => None,
+            };
+            add(opt, value)?;
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            for (i, short) in shorts.char_indices() {
+                let opt = spec
+                    .by_short(short)
+                    .ok_or_else(|| ConfigError::UnknownOption(format!("-{short}")))?;
+                if !opt.takes_value() {
+                    add(opt, None)?;
+                    continue;
+                }
+                let rest = &shorts[i + short.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next()
+                        .ok_or_else(|| ConfigError::MissingValue(format!("-{short}")))?
+                } else {
+                    rest.to_owned()
+                };
+                add(opt, Some(value))?;
+                break;
+            }
+        } else if let Some(opt) = spec.operands() {
+            add(opt, Some(arg))?;
+        } else {
+            return Err(ConfigError::UnexpectedArgument(arg));
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: Spec = Spec {
+        program: "config-test",
+        about: "Exercises the option parser.",
+        applet: "config-test",
+        env_prefix: "CONFIG_TEST",
+        options: &[
+            Opt::value("port", Some('p'), "PORT", "Port").default("6202"),
+            Opt::flag("overwrite", Some('o'), "Overwrite"),
+            Opt::list("cacher", Some('c'), "ADDRESS", "Cachers"),
+            Opt::operands("name", "NAME", "Names"),
+            Opt::count("verbose", Some('v'), "Verbosity"),
+        ],
+    };
+
+    fn load(args: &[&str], env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect();
+        Config::from_sources(&SPEC, args.iter().map(|arg| arg.to_string()), |var| {
+            env.get(var).cloned()
+        })
+    }
+
+    /// Writes `text` to a config file unique to `test` and returns its path.
+    fn config_file(test: &str, text: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("config-test-{}-{test}.toml", process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn defaults_apply_when_nothing_is_set() {
+        let config = load(&[], &[]).unwrap();
+        assert_eq!(config.get("port"), Some("6202"));
+        assert_eq!(config.require::<u16>("port").unwrap(), 6202);
+        assert!(!config.flag("overwrite"));
+        assert!(config.list("cacher").is_empty());
+        assert_eq!(config.count("verbose"), 0);
+    }
+
+    #[test]
+    fn cli_beats_env_beats_file() {
+        let path = config_file(
+            "precedence",
+            "port = 1\ncacher = [\"file:1\"]\noverwrite = true\n",
+        );
+        let path_arg = path.to_str().unwrap();
+
+        let config = load(&["--config", path_arg], &[]).unwrap();
+        assert_eq!(config.get("port"), Some("1"));
+        assert_eq!(config.list("cacher"), ["file:1"]);
+        assert!(config.flag("overwrite"));
+
+        let env = [("CONFIG_TEST_PORT", "2"), ("CONFIG_TEST_OVERWRITE", "no")];
+        let config = load(&["--config", path_arg], &env).unwrap();
+        assert_eq!(config.get("port"), Some("2"));
+        assert_eq!(config.list("cacher"), ["file:1"]);
+        assert!(!config.flag("overwrite"));
+
+        let config = load(&["--config", path_arg, "-p", "3", "-o"], &env).unwrap();
+        assert_eq!(config.get("port"), Some("3"));
+        assert!(config.flag("overwrite"));
+
+        // The file may also be named through the environment.
+        let config = load(&[], &[("CONFIG_TEST_CONFIG", path_arg)]).unwrap();
+        assert_eq!(config.get("port"), Some("1"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn invalid_values_name_their_source() {
+        let config = load(&[], &[("CONFIG_TEST_PORT", "lots")]).unwrap();
+        match config.require::<u16>("port") {
+            Err(ConfigError::Invalid { option, source, .. }) => {
+                assert_eq!(option, "port");
+                assert_eq!(source, Source::Env("CONFIG_TEST_PORT".to_owned()));
+            }
+            other => panic!("expected an invalid value, got {other:?}"),
+        }
+        assert!(matches!(
+            load(&["--overwrite=maybe"], &[]),
+            Err(ConfigError::UnexpectedValue(_))
+        ));
+        assert!(matches!(
+            load(&[], &[("CONFIG_TEST_OVERWRITE", "maybe")]),
+            Err(ConfigError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn lists_keep_every_repeat() {
+        let config = load(
+            &["-c", "a:1", "--cacher", "b:2", "--cacher=c:3", "-cd:4"],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(config.list("cacher"), ["a:1", "b:2", "c:3", "d:4"]);
+
+        let config = load(&[], &[("CONFIG_TEST_CACHER", "a:1,b:2")]).unwrap();
+        assert_eq!(config.list("cacher"), ["a:1", "b:2"]);
+
+        // Lists from the command line replace, rather than extend, the env.
+        let config = load(&["-c", "c:3"], &[("CONFIG_TEST_CACHER", "a:1,b:2")]).unwrap();
+        assert_eq!(config.list("cacher"), ["c:3"]);
+    }
+
+    #[test]
+    fn operands_collect_bare_arguments() {
+        let config = load(&["example.com", "-v", "shop.com"], &[]).unwrap();
+        assert_eq!(config.list("name"), ["example.com", "shop.com"]);
+    }
+
+    #[test]
+    fn counts_add_up_repeats() {
+        assert_eq!(load(&["-v"], &[]).unwrap().count("verbose"), 1);
+        assert_eq!(load(&["-vvv"], &[]).unwrap().count("verbose"), 3);
+        assert_eq!(
+            load(&["-v", "--verbose", "-ov"], &[])
+                .unwrap()
+                .count("verbose"),
+            3
+        );
+        assert_eq!(
+            load(&[], &[("CONFIG_TEST_VERBOSE", "2")])
+                .unwrap()
+                .count("verbose"),
+            2
+        );
+        assert_eq!(
+            load(&[], &[("CONFIG_TEST_VERBOSE", "true")])
+                .unwrap()
+                .count("verbose"),
+            1
+        );
+    }
+
+    #[test]
+    fn unknown_options_are_errors() {
+        assert!(matches!(
+            load(&["--colour"], &[]),
+            Err(ConfigError::UnknownOption(opt)) if opt == "--colour"
+        ));
+        assert!(matches!(
+            load(&["-x"], &[]),
+            Err(ConfigError::UnknownOption(opt)) if opt == "-x"
+        ));
+        assert!(matches!(
+            load(&["--port"], &[]),
+            Err(ConfigError::MissingValue(opt)) if opt == "--port"
+        ));
+        let path = config_file("unknown", "colour = \"blue\"\n");
+        assert!(matches!(
+            load(&["--config", path.to_str().unwrap()], &[]),
+            Err(ConfigError::Toml { .. })
+        ));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn help_carries_the_usage() {
+        match load(&["-p", "1", "--help"], &[]) {
+            Err(ConfigError::Help(usage)) => {
+                assert!(usage.starts_with("Usage: config-test [OPTIONS] [NAME]..."));
+                assert!(usage.contains("--port <PORT>"));
+                assert!(usage.contains("[default: 6202]"));
+            }
+            other => panic!("expected help, got {other:?}"),
+        }
+    }
+}
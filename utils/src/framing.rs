@@ -0,0 +1,242 @@
+//! Length-prefixed message framing shared by every service.
+//!
+//! A message is sent as a sequence of blocks, each prefixed by its length as a
+//! little-endian `u16`. A block shorter than `u16::MAX` ends the message; a
+//! message whose length is a multiple of `u16::MAX` is ended by an empty block.
+
+use crate::Status;
+use async_std::io::{self as async_io, ReadExt, WriteExt};
+use futures::{AsyncRead, AsyncWrite};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+use tracing::{debug, trace};
+
+const BLOCK_LEN: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLimits {
+    /// Largest message that will be accepted. Reading stops as soon as a block
+    /// announces that this would be exceeded, before anything is allocated.
+    pub max_size: usize,
+    /// Deadline applied to every individual read, `None` to wait forever.
+    pub read_timeout: Option<Duration>,
+}
+
+impl FrameLimits {
+    pub const fn new(max_size: usize, read_timeout: Option<Duration>) -> Self {
+        Self {
+            max_size,
+            read_timeout,
+        }
+    }
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        Self::new(16 * 1024 * 1024, Some(Duration::from_secs(30)))
+    }
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    /// The peer closed the connection before sending anything.
+    Closed,
+    /// The connection ended part way through a message.
+    Truncated {
+        received: usize,
+    },
+    /// The peer announced more data than `FrameLimits::max_size` allows.
+    TooLarge {
+        limit: usize,
+    },
+    /// A read did not complete within `FrameLimits::read_timeout`.
+    TimedOut,
+    /// A compressed message could not be decompressed.
+    Corrupt(io::Error),
+    Io(io::Error),
+}
+
+impl FrameError {
+    /// Status code to report back to the peer, if it is worth telling them.
+    pub fn status(&self) -> Option<Status> {
+        match self {
+            FrameError::TooLarge { .. } => Some(Status::TooLarge),
+            FrameError::Truncated { .. } | FrameError::Corrupt(_) => Some(Status::BadRequest),
+            FrameError::Closed | FrameError::TimedOut | FrameError::Io(_) => None,
+        }
+    }
+
+    fn from_read(e: io::Error, received: usize, at_boundary: bool) -> Self {
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => FrameError::TimedOut,
+            io::ErrorKind::UnexpectedEof if received == 0 && at_boundary => FrameError::Closed,
+            io::ErrorKind::UnexpectedEof => FrameError::Truncated { received },
+            _ => FrameError::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Closed => write!(f, "connection closed by peer"),
+            FrameError::Truncated { received } => {
+                write!(f, "message truncated after {received} bytes")
+            }
+            FrameError::TooLarge { limit } => write!(f, "message exceeds {limit} bytes"),
+            FrameError::TimedOut => write!(f, "timed out waiting for data"),
+            FrameError::Corrupt(e) => write!(f, "message could not be decompressed: {e}"),
+            FrameError::Io(e) => write!(f, "I/O error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FrameError::Corrupt(e) | FrameError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+pub fn receive_data(mut stream: &TcpStream, limits: &FrameLimits) -> Result<Vec<u8>, FrameError> {
+    trace!("Started receiving data.");
+    stream.set_read_timeout(limits.read_timeout)?;
+    let mut len = [0; 2];
+    let mut data = Vec::new();
+    loop {
+        stream
+            .read_exact(&mut len)
+            .map_err(|e| FrameError::from_read(e, data.len(), true))?;
+        let len = u16::from_le_bytes(len) as usize;
+        if len == 0 {
+            trace!("Received null terminator.");
+            break;
+        }
+        if data.len() + len > limits.max_size {
+            debug!(
+                "Refusing block of {} bytes after {} received; limit is {}.",
+                len,
+                data.len(),
+                limits.max_size
+            );
+            return Err(FrameError::TooLarge {
+                limit: limits.max_size,
+            });
+        }
+        trace!("Expecting {len} bytes...");
+        let start = data.len();
+        data.resize(start + len, 0);
+        stream
+            .read_exact(&mut data[start..])
+            .map_err(|e| FrameError::from_read(e, start, false))?;
+        trace!("Received block of size {}.", len);
+        if len != BLOCK_LEN {
+            break;
+        }
+        trace!("Expecting another block...");
+    }
+    debug!("Finished receiving data of size {}", data.len());
+    Ok(data)
+}
+
+pub fn send_data(payload: &[u8], mut stream: &TcpStream) -> Result<(), FrameError> {
+    debug!("Started sending data of size {}", payload.len());
+    for block in payload.chunks(BLOCK_LEN) {
+        let message_len = block.len() as u16;
+        trace!("Sending block of size {}...", message_len);
+        stream.write_all(&message_len.to_le_bytes())?;
+        stream.write_all(block)?;
+    }
+    if payload.len().is_multiple_of(BLOCK_LEN) {
+        trace!("Sending null terminator");
+        stream.write_all(&0u16.to_le_bytes())?;
+    }
+    trace!("Finished sending data.");
+    Ok(())
+}
+
+pub async fn receive_data_async<S: AsyncRead + Unpin>(
+    mut stream: S,
+    limits: &FrameLimits,
+) -> Result<Vec<u8>, FrameError> {
+    trace!("Started receiving data.");
+    let mut len = [0; 2];
+    let mut data = Vec::new();
+    loop {
+        read_exact_within(&mut stream, &mut len, limits.read_timeout)
+            .await
+            .map_err(|e| FrameError::from_read(e, data.len(), true))?;
+        let len = u16::from_le_bytes(len) as usize;
+        if len == 0 {
+            trace!("Received null terminator.");
+            break;
+        }
+        if data.len() + len > limits.max_size {
+            debug!(
+                "Refusing block of {} bytes after {} received; limit is {}.",
+                len,
+                data.len(),
+                limits.max_size
+            );
+            return Err(FrameError::TooLarge {
+                limit: limits.max_size,
+            });
+        }
+        trace!("Expecting {len} bytes...");
+        let start = data.len();
+        data.resize(start + len, 0);
+        read_exact_within(&mut stream, &mut data[start..], limits.read_timeout)
+            .await
+            .map_err(|e| FrameError::from_read(e, start, false))?;
+        trace!("Received block of size {}.", len);
+        if len != BLOCK_LEN {
+            break;
+        }
+        trace!("Expecting another block...");
+    }
+    debug!("Finished receiving data of size {}", data.len());
+    Ok(data)
+}
+
+pub async fn send_data_async<S: AsyncWrite + Unpin>(
+    payload: &[u8],
+    mut stream: S,
+) -> Result<(), FrameError> {
+    debug!("Started sending data of size {}", payload.len());
+    for block in payload.chunks(BLOCK_LEN) {
+        let message_len = block.len() as u16;
+        trace!("Sending block of size {}...", message_len);
+        stream.write_all(&message_len.to_le_bytes()).await?;
+        stream.write_all(block).await?;
+    }
+    if payload.len().is_multiple_of(BLOCK_LEN) {
+        trace!("Sending null terminator");
+        stream.write_all(&0u16.to_le_bytes()).await?;
+    }
+    stream.flush().await?;
+    trace!("Finished sending data.");
+    Ok(())
+}
+
+async fn read_exact_within<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buf: &mut [u8],
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    match timeout {
+        Some(timeout) => async_io::timeout(timeout, stream.read_exact(buf)).await,
+        None => stream.read_exact(buf).await,
+    }
+}
@@ -0,0 +1,349 @@
+//! Opening exchange on every connection.
+//!
+//! The client sends a `ClientHello` with the range of protocol versions it
+//! speaks. The server picks the highest version both sides support and answers
+//! with a `ServerHello`, or with a bare `UpgradeRequired`/`DowngradeRequired`
+//! status and closes the connection when the ranges do not overlap. Requests
+//! only follow a successful handshake.
+//!
+//! The client's hello also carries the `Capabilities` it supports, and the
+//! server answers with the subset it agrees to use, keeping at most one
+//! compression algorithm. Optional features are only used on a connection
+//! when both sides advertised them.
+
+use crate::{
+    Status,
+    compression::Compression,
+    framing::{FrameError, FrameLimits, receive_data_async, send_data_async},
+    protocol::{DecodeError, Version, read_status},
+    send_error_async,
+};
+use futures::{AsyncRead, AsyncWrite};
+use std::{
+    fmt,
+    net::SocketAddr,
+    ops::{BitAnd, BitOr},
+};
+use tracing::debug;
+
+/// Oldest protocol version this build can speak.
+pub const PROTOCOL_MIN: Version = Version::new(0, 2, 0);
+/// Newest protocol version this build can speak.
+pub const PROTOCOL_MAX: Version = Version::new(0, 2, 0);
+
+/// Set of optional protocol features, sent as a little-endian `u32` bitmask.
+///
+/// Bits this build does not know about are kept when decoding, but never
+/// survive negotiation since we cannot have advertised them ourselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// Messages may be compressed with deflate.
+    pub const DEFLATE: Self = Self(1 << 0);
+    // Bits 1 and 2 are free for features yet to be implemented.
+    /// Messages may be compressed with zstd.
+    pub const ZSTD: Self = Self(1 << 3);
+    /// Any number of requests may follow the handshake, until the client
+    /// closes the connection or leaves it idle.
+    pub const KEEP_ALIVE: Self = Self(1 << 4);
+    /// Lookup answers say how many seconds they stay valid.
+    pub const TTL: Self = Self(1 << 5);
+    /// A delegation may name several DNS providers, separated by commas, to
+    /// be tried in order.
+    pub const ALTERNATES: Self = Self(1 << 6);
+
+    const NAMES: [(Self, &'static str); 5] = [
+        (Self::DEFLATE, "deflate"),
+        (Self::ZSTD, "zstd"),
+        (Self::KEEP_ALIVE, "keep-alive"),
+        (Self::TTL, "ttl"),
+        (Self::ALTERNATES, "alternates"),
+    ];
+
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// This set without any of the capabilities in `other`.
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    fn decode(data: &[u8]) -> Self {
+        match data.get(..4) {
+            Some(bytes) => Self(u32::from_le_bytes(bytes.try_into().unwrap())),
+            None => Self::NONE,
+        }
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+        let mut rest = *self;
+        let mut first = true;
+        for (capability, name) in Self::NAMES {
+            if self.contains(capability) {
+                write!(f, "{}{}", if first { "" } else { ", " }, name)?;
+                rest = rest.difference(capability);
+                first = false;
+            }
+        }
+        if !rest.is_empty() {
+            write!(f, "{}{:#x}", if first { "" } else { ", " }, rest.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// What both ends of a connection agreed on during the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    pub version: Version,
+    pub capabilities: Capabilities,
+}
+
+impl Session {
+    /// Compression applied to every message after the handshake.
+    pub fn compression(&self) -> Compression {
+        Compression::negotiate(self.capabilities)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientHello {
+    pub min: Version,
+    pub max: Version,
+    pub capabilities: Capabilities,
+}
+
+impl ClientHello {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(28);
+        self.min.encode(&mut payload);
+        self.max.encode(&mut payload);
+        payload.extend_from_slice(&self.capabilities.bits().to_le_bytes());
+        payload
+    }
+
+    /// Decodes a client's opening message. Clients predating capabilities send
+    /// only the version range and are treated as advertising none.
+    pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        let (min, data) = Version::decode(data)?;
+        let (max, data) = Version::decode(data)?;
+        if min > max {
+            return Err(DecodeError::Malformed("version range is inverted"));
+        }
+        Ok(Self {
+            min,
+            max,
+            capabilities: Capabilities::decode(data),
+        })
+    }
+
+    /// Highest version inside both this range and `min..=max`, or the status
+    /// explaining which side needs to move when they do not overlap.
+    pub fn negotiate(&self, min: Version, max: Version) -> Result<Version, Status> {
+        if self.max < min {
+            Err(Status::UpgradeRequired)
+        } else if self.min > max {
+            Err(Status::DowngradeRequired)
+        } else {
+            Ok(self.max.min(max))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerHello {
+    pub version: Version,
+    pub capabilities: Capabilities,
+}
+
+impl ServerHello {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Status::Success.code().to_le_bytes().to_vec();
+        self.version.encode(&mut payload);
+        payload.extend_from_slice(&self.capabilities.bits().to_le_bytes());
+        payload
+    }
+
+    /// Decodes the server's answer. A bare status means the server refused the
+    /// handshake and is returned as `HandshakeError::Rejected`.
+    pub fn decode(data: &[u8]) -> Result<Self, HandshakeError> {
+        let status = read_status(data)?;
+        if status != Status::Success {
+            return Err(HandshakeError::Rejected(status));
+        }
+        let (version, data) = Version::decode(&data[4..])?;
+        Ok(Self {
+            version,
+            capabilities: Capabilities::decode(data),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    Frame(FrameError),
+    Decode(DecodeError),
+    /// The peer does not speak any version in our range; carries the status
+    /// sent to (or received from) the peer.
+    Rejected(Status),
+}
+
+impl HandshakeError {
+    /// Status to report to callers of a client, e.g. `UpgradeRequired`.
+    pub fn status(&self) -> Status {
+        match self {
+            HandshakeError::Frame(FrameError::TimedOut) => Status::TimedOut,
+            HandshakeError::Frame(_) => Status::HostUnreachable,
+            HandshakeError::Decode(_) => Status::BadResponse,
+            HandshakeError::Rejected(status) => *status,
+        }
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Frame(e) => write!(f, "handshake failed: {e}"),
+            HandshakeError::Decode(e) => write!(f, "handshake failed: {e}"),
+            HandshakeError::Rejected(status) => write!(f, "handshake rejected: {status}"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HandshakeError::Frame(e) => Some(e),
+            HandshakeError::Decode(e) => Some(e),
+            HandshakeError::Rejected(status) => Some(status),
+        }
+    }
+}
+
+impl From<FrameError> for HandshakeError {
+    fn from(e: FrameError) -> Self {
+        HandshakeError::Frame(e)
+    }
+}
+
+impl From<DecodeError> for HandshakeError {
+    fn from(e: DecodeError) -> Self {
+        HandshakeError::Decode(e)
+    }
+}
+
+/// Opens a connection from the client side, advertising `capabilities`.
+pub async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    capabilities: Capabilities,
+    limits: &FrameLimits,
+) -> Result<Session, HandshakeError> {
+    let hello = ClientHello {
+        min: PROTOCOL_MIN,
+        max: PROTOCOL_MAX,
+        capabilities,
+    };
+    send_data_async(&hello.encode(), &mut stream).await?;
+    let response = receive_data_async(&mut stream, limits).await?;
+    let server = ServerHello::decode(&response)?;
+    let session = Session {
+        version: server.version,
+        capabilities: server.capabilities & capabilities,
+    };
+    debug!(
+        "Negotiated protocol {} with capabilities: {}",
+        session.version, session.capabilities
+    );
+    Ok(session)
+}
+
+/// Answers a client's opening message, agreeing to whichever of
+/// `capabilities` the client also advertised. When no common version exists
+/// the client is told which way to move and the connection is closed.
+pub async fn server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    peer: SocketAddr,
+    capabilities: Capabilities,
+    limits: &FrameLimits,
+) -> Result<Session, HandshakeError> {
+    let data = receive_data_async(&mut stream, limits).await?;
+    let hello = match ClientHello::decode(&data) {
+        Ok(hello) => hello,
+        Err(e) => {
+            send_error_async(&mut stream, e.status()).await;
+            return Err(e.into());
+        }
+    };
+    match hello.negotiate(PROTOCOL_MIN, PROTOCOL_MAX) {
+        Ok(version) => {
+            let shared = hello.capabilities & capabilities;
+            let compression = Compression::negotiate(shared);
+            let session = Session {
+                version,
+                capabilities: shared.difference(Compression::ALL) | compression.capability(),
+            };
+            debug!(
+                "Connection from {}:{} negotiated protocol {} with capabilities: {}",
+                peer.ip(),
+                peer.port(),
+                session.version,
+                session.capabilities
+            );
+            let hello = ServerHello {
+                version,
+                capabilities: session.capabilities,
+            };
+            send_data_async(&hello.encode(), &mut stream).await?;
+            Ok(session)
+        }
+        Err(status) => {
+            debug!(
+                "Connection from {}:{} used an incompatible protocol: {}-{}, expected {}-{}",
+                peer.ip(),
+                peer.port(),
+                hello.min,
+                hello.max,
+                PROTOCOL_MIN,
+                PROTOCOL_MAX
+            );
+            send_error_async(&mut stream, status).await;
+            Err(HandshakeError::Rejected(status))
+        }
+    }
+}
@@ -0,0 +1,1059 @@
+use crate::Status;
+use std::{fmt, str::FromStr};
+
+/// Length in bytes of a stack name as sent to the content server.
+pub const STACK_LEN: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub(crate) fn encode(&self, payload: &mut Vec<u8>) {
+        payload.extend_from_slice(&self.major.to_le_bytes());
+        payload.extend_from_slice(&self.minor.to_le_bytes());
+        payload.extend_from_slice(&self.patch.to_le_bytes());
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> Result<(Self, &[u8]), DecodeError> {
+        let major = read_u32(data, 0)?;
+        let minor = read_u32(data, 4)?;
+        let patch = read_u32(data, 8)?;
+        Ok((Self::new(major, minor, patch), &data[12..]))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.').map(|part| {
+            part.parse::<u32>()
+                .map_err(|_| DecodeError::Malformed("version component is not a number"))
+        });
+        let mut next = || {
+            parts.next().unwrap_or(Err(DecodeError::Malformed(
+                "version has fewer than 3 parts",
+            )))
+        };
+        Ok(Self::new(next()?, next()?, next()?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    TooShort { expected: usize, actual: usize },
+    Malformed(&'static str),
+}
+
+impl DecodeError {
+    /// Status code to report back to a peer whose request failed to decode.
+    pub fn status(&self) -> Status {
+        match self {
+            DecodeError::TooShort { .. } => Status::TooSmall,
+            DecodeError::Malformed(_) => Status::Unprocessable,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TooShort { expected, actual } => {
+                write!(
+                    f,
+                    "payload too short: expected {expected} bytes, got {actual}"
+                )
+            }
+            DecodeError::Malformed(reason) => write!(f, "malformed payload: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A message that cannot be put on the wire as it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// A fixed-size field, e.g. a stack name, has the wrong length.
+    WrongLength {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// A string or list has more bytes or entries than its length prefix
+    /// can count.
+    TooLong {
+        field: &'static str,
+        max: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::WrongLength {
+                field,
+                expected,
+                actual,
+            } => write!(f, "{field} is {actual} bytes, expected {expected}"),
+            EncodeError::TooLong { field, max, actual } => {
+                write!(f, "{field} is too long: {actual}, at most {max} fit")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+pub(crate) fn read_status(data: &[u8]) -> Result<Status, DecodeError> {
+    read_u32(data, 0).map(Status::from)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, DecodeError> {
+    match data.get(at..at + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap())),
+        None => Err(DecodeError::TooShort {
+            expected: at + 4,
+            actual: data.len(),
+        }),
+    }
+}
+
+/// Answer shared by the DNS provider and the DNS cacher: either a bare status
+/// code, or a status code followed by an `ip:port` address.
+///
+/// On sessions that negotiated `Capabilities::TTL`, an address is preceded by
+/// the number of seconds it stays valid, with `NO_TTL` standing for unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupResponse {
+    Status(Status),
+    Address {
+        status: Status,
+        address: String,
+        ttl: Option<u32>,
+    },
+}
+
+impl LookupResponse {
+    /// Wire value of an address whose lifetime is unknown.
+    pub const NO_TTL: u32 = u32::MAX;
+
+    pub fn status(&self) -> Status {
+        match self {
+            LookupResponse::Status(status) => *status,
+            LookupResponse::Address { status, .. } => *status,
+        }
+    }
+
+    pub fn encode(&self, with_ttl: bool) -> Vec<u8> {
+        match self {
+            LookupResponse::Status(status) => status.code().to_le_bytes().to_vec(),
+            LookupResponse::Address {
+                status,
+                address,
+                ttl,
+            } => {
+                let mut payload = status.code().to_le_bytes().to_vec();
+                if with_ttl {
+                    payload.extend_from_slice(&ttl.unwrap_or(Self::NO_TTL).to_le_bytes());
+                }
+                payload.extend_from_slice(address.as_bytes());
+                payload
+            }
+        }
+    }
+
+    pub fn decode(data: &[u8], with_ttl: bool) -> Result<Self, DecodeError> {
+        let status = read_status(data)?;
+        if data.len() == 4 {
+            return Ok(LookupResponse::Status(status));
+        }
+        let (ttl, address) = if with_ttl {
+            let ttl = read_u32(data, 4)?;
+            (Some(ttl).filter(|&ttl| ttl != Self::NO_TTL), &data[8..])
+        } else {
+            (None, &data[4..])
+        };
+        Ok(LookupResponse::Address {
+            status,
+            address: String::from_utf8_lossy(address).into_owned(),
+            ttl,
+        })
+    }
+}
+
+pub mod provider {
+    use super::{DecodeError, EncodeError, admin::Signed};
+
+    pub type Response = super::LookupResponse;
+
+    /// Leads a `Manage` request, after the two block flags of `Resolve`.
+    const MANAGE: u8 = 2;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Request {
+        /// Resolve a single block of an FQDN. `last_block` is set when no
+        /// further blocks follow, i.e. the client wants a domain and not a
+        /// delegation.
+        Resolve { last_block: bool, block: String },
+        /// Change or list the provider's records. Answered with an
+        /// `admin::Response` instead of a `Response`.
+        Manage(Signed),
+    }
+
+    impl Request {
+        /// Fails if a `Manage` request does not fit its length prefixes.
+        pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+            match self {
+                Request::Resolve { last_block, block } => {
+                    let mut payload = Vec::with_capacity(1 + block.len());
+                    payload.push(if *last_block { 0 } else { 1 });
+                    payload.extend_from_slice(block.as_bytes());
+                    Ok(payload)
+                }
+                Request::Manage(signed) => {
+                    let mut payload = vec![MANAGE];
+                    payload.extend_from_slice(&signed.encode()?);
+                    Ok(payload)
+                }
+            }
+        }
+
+        pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+            if data.len() < 2 {
+                return Err(DecodeError::TooShort {
+                    expected: 2,
+                    actual: data.len(),
+                });
+            }
+            let last_block = match data[0] {
+                0 => true,
+                1 => false,
+                MANAGE => return Signed::decode(&data[1..]).map(Request::Manage),
+                _ => return Err(DecodeError::Malformed("unknown block flag")),
+            };
+            Ok(Request::Resolve {
+                last_block,
+                block: String::from_utf8_lossy(&data[1..]).into_owned(),
+            })
+        }
+    }
+}
+
+pub mod admin {
+    //! Requests that change a DNS provider's records rather than look them up.
+    //!
+    //! Every `Command` travels in a `Signed` envelope carrying an HMAC made
+    //! with a key the provider shares with the client; see `crate::auth`.
+
+    use super::{DecodeError, EncodeError, read_status};
+    use crate::Status;
+    use std::fmt;
+
+    /// Length in bytes of the random nonce in a `Signed` request.
+    pub const NONCE_LEN: usize = 16;
+    /// Length in bytes of an HMAC-SHA256 tag.
+    pub const MAC_LEN: usize = 32;
+
+    /// One row of `dns_records`. `name` is `.` for the record that moves the
+    /// whole provider (`dns`) or answers unknown names (`domain`).
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Record {
+        pub name: String,
+        /// `ip` and port of the site itself.
+        pub domain: Option<(String, u16)>,
+        /// `ip` and port of the DNS providers names below this one are
+        /// delegated to, in the order clients should try them. Empty when
+        /// nothing is delegated.
+        pub dns: Vec<(String, u16)>,
+        pub ttl: u32,
+    }
+
+    impl Record {
+        fn encode(&self, payload: &mut Vec<u8>) -> Result<(), EncodeError> {
+            write_str(payload, "name", &self.name)?;
+            match &self.domain {
+                None => payload.push(0),
+                Some(address) => {
+                    payload.push(1);
+                    write_address(payload, address)?;
+                }
+            }
+            let count = u8::try_from(self.dns.len()).map_err(|_| EncodeError::TooLong {
+                field: "dns",
+                max: u8::MAX as usize,
+                actual: self.dns.len(),
+            })?;
+            payload.push(count);
+            for address in &self.dns {
+                write_address(payload, address)?;
+            }
+            payload.extend_from_slice(&self.ttl.to_le_bytes());
+            Ok(())
+        }
+
+        fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+            let name = reader.string()?;
+            let domain = match reader.u8()? {
+                0 => None,
+                1 => Some(reader.address()?),
+                _ => return Err(DecodeError::Malformed("unknown address flag")),
+            };
+            let dns = (0..reader.u8()?)
+                .map(|_| reader.address())
+                .collect::<Result<_, _>>()?;
+            Ok(Record {
+                name,
+                domain,
+                dns,
+                ttl: reader.u32()?,
+            })
+        }
+    }
+
+    impl fmt::Display for Record {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let domain = self
+                .domain
+                .as_ref()
+                .map_or("-".to_owned(), |(ip, port)| format!("{ip}:{port}"));
+            let dns = if self.dns.is_empty() {
+                "-".to_owned()
+            } else {
+                self.dns
+                    .iter()
+                    .map(|(ip, port)| format!("{ip}:{port}"))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            write!(f, "{}\t{}\t{}\t{}", self.name, self.ttl, domain, dns)
+        }
+    }
+
+    /// The records a secondary provider needs to catch up with a primary.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Transfer {
+        /// The primary's serial once these changes are applied.
+        pub serial: u64,
+        /// Whether `records` is the whole zone, replacing everything the
+        /// secondary has, rather than the records changed since its serial.
+        pub full: bool,
+        pub records: Vec<Record>,
+        /// Names removed since the secondary's serial. Empty when `full`.
+        pub deleted: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Command {
+        /// Every record the key may manage.
+        List,
+        Get {
+            name: String,
+        },
+        /// Create the record, or replace the one with the same name.
+        Put(Record),
+        Delete {
+            name: String,
+        },
+        /// Every change since serial `since`, or the whole zone when `since`
+        /// is 0 or too old to know what changed.
+        Transfer {
+            since: u64,
+        },
+    }
+
+    impl Command {
+        /// The record the command touches, `None` for `List` and `Transfer`.
+        pub fn name(&self) -> Option<&str> {
+            match self {
+                Command::List | Command::Transfer { .. } => None,
+                Command::Get { name } | Command::Delete { name } => Some(name),
+                Command::Put(record) => Some(&record.name),
+            }
+        }
+
+        fn encode(&self, payload: &mut Vec<u8>) -> Result<(), EncodeError> {
+            match self {
+                Command::List => payload.push(0),
+                Command::Get { name } => {
+                    payload.push(1);
+                    write_str(payload, "name", name)?;
+                }
+                Command::Put(record) => {
+                    payload.push(2);
+                    record.encode(payload)?;
+                }
+                Command::Delete { name } => {
+                    payload.push(3);
+                    write_str(payload, "name", name)?;
+                }
+                Command::Transfer { since } => {
+                    payload.push(4);
+                    payload.extend_from_slice(&since.to_le_bytes());
+                }
+            }
+            Ok(())
+        }
+
+        fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+            match reader.u8()? {
+                0 => Ok(Command::List),
+                1 => Ok(Command::Get {
+                    name: reader.string()?,
+                }),
+                2 => Ok(Command::Put(Record::decode(reader)?)),
+                3 => Ok(Command::Delete {
+                    name: reader.string()?,
+                }),
+                4 => Ok(Command::Transfer {
+                    since: reader.u64()?,
+                }),
+                _ => Err(DecodeError::Malformed("unknown command")),
+            }
+        }
+    }
+
+    /// A `Command` authenticated with the pre-shared key `key_id`. `timestamp`
+    /// (Unix seconds) and `nonce` keep a captured request from being replayed.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Signed {
+        pub key_id: String,
+        pub timestamp: u64,
+        pub nonce: [u8; NONCE_LEN],
+        pub command: Command,
+        /// HMAC-SHA256 over `body()`.
+        pub mac: Vec<u8>,
+    }
+
+    impl Signed {
+        /// The bytes covered by `mac`: everything but the tag itself. Fails
+        /// if a string or list does not fit its length prefix.
+        pub fn body(&self) -> Result<Vec<u8>, EncodeError> {
+            let mut payload = Vec::new();
+            write_str(&mut payload, "key id", &self.key_id)?;
+            payload.extend_from_slice(&self.timestamp.to_le_bytes());
+            payload.extend_from_slice(&self.nonce);
+            self.command.encode(&mut payload)?;
+            Ok(payload)
+        }
+
+        pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+            let mut payload = self.body()?;
+            payload.extend_from_slice(&self.mac);
+            Ok(payload)
+        }
+
+        pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+            let Some(split) = data.len().checked_sub(MAC_LEN) else {
+                return Err(DecodeError::TooShort {
+                    expected: MAC_LEN,
+                    actual: data.len(),
+                });
+            };
+            let (body, mac) = data.split_at(split);
+            let mut reader = Reader { data: body, at: 0 };
+            let signed = Signed {
+                key_id: reader.string()?,
+                timestamp: reader.u64()?,
+                nonce: reader.bytes(NONCE_LEN)?.try_into().unwrap(),
+                command: Command::decode(&mut reader)?,
+                mac: mac.to_vec(),
+            };
+            if reader.at != body.len() {
+                return Err(DecodeError::Malformed("trailing bytes after command"));
+            }
+            Ok(signed)
+        }
+    }
+
+    /// Sent after the status code to tell a `Records` answer from a
+    /// `Transfer`.
+    const RECORDS: u8 = 0;
+    const TRANSFER: u8 = 1;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Response {
+        Status(Status),
+        /// Answer to `List` and `Get`.
+        Records(Vec<Record>),
+        /// Answer to `Transfer`.
+        Transfer(Transfer),
+    }
+
+    impl Response {
+        pub fn status(&self) -> Status {
+            match self {
+                Response::Status(status) => *status,
+                Response::Records(_) | Response::Transfer(_) => Status::Success,
+            }
+        }
+
+        /// Fails if a record does not fit its length prefixes.
+        pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+            let mut payload = self.status().code().to_le_bytes().to_vec();
+            match self {
+                Response::Status(_) => {}
+                Response::Records(records) => {
+                    payload.push(RECORDS);
+                    write_records(&mut payload, records)?;
+                }
+                Response::Transfer(transfer) => {
+                    payload.push(TRANSFER);
+                    payload.extend_from_slice(&transfer.serial.to_le_bytes());
+                    payload.push(transfer.full as u8);
+                    write_records(&mut payload, &transfer.records)?;
+                    write_count(&mut payload, "deleted", transfer.deleted.len())?;
+                    for name in &transfer.deleted {
+                        write_str(&mut payload, "name", name)?;
+                    }
+                }
+            }
+            Ok(payload)
+        }
+
+        pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+            let status = read_status(data)?;
+            if data.len() == 4 {
+                return Ok(Response::Status(status));
+            }
+            let mut reader = Reader { data, at: 4 };
+            match reader.u8()? {
+                RECORDS => Ok(Response::Records(reader.records()?)),
+                TRANSFER => {
+                    let serial = reader.u64()?;
+                    let full = reader.u8()? != 0;
+                    let records = reader.records()?;
+                    let deleted = (0..reader.u32()?)
+                        .map(|_| reader.string())
+                        .collect::<Result<_, _>>()?;
+                    Ok(Response::Transfer(Transfer {
+                        serial,
+                        full,
+                        records,
+                        deleted,
+                    }))
+                }
+                _ => Err(DecodeError::Malformed("unknown response kind")),
+            }
+        }
+    }
+
+    fn write_records(payload: &mut Vec<u8>, records: &[Record]) -> Result<(), EncodeError> {
+        write_count(payload, "records", records.len())?;
+        for record in records {
+            record.encode(payload)?;
+        }
+        Ok(())
+    }
+
+    /// Lists are led by a four-byte count.
+    fn write_count(
+        payload: &mut Vec<u8>,
+        field: &'static str,
+        count: usize,
+    ) -> Result<(), EncodeError> {
+        let count = u32::try_from(count).map_err(|_| EncodeError::TooLong {
+            field,
+            max: u32::MAX as usize,
+            actual: count,
+        })?;
+        payload.extend_from_slice(&count.to_le_bytes());
+        Ok(())
+    }
+
+    fn write_address(payload: &mut Vec<u8>, (ip, port): &(String, u16)) -> Result<(), EncodeError> {
+        write_str(payload, "address", ip)?;
+        payload.extend_from_slice(&port.to_le_bytes());
+        Ok(())
+    }
+
+    /// Strings are sent with a one-byte length, which fits every name and
+    /// address `dns_records` is meant to hold. Longer ones are refused rather
+    /// than cut, which would change what they name.
+    fn write_str(payload: &mut Vec<u8>, field: &'static str, s: &str) -> Result<(), EncodeError> {
+        let len = u8::try_from(s.len()).map_err(|_| EncodeError::TooLong {
+            field,
+            max: u8::MAX as usize,
+            actual: s.len(),
+        })?;
+        payload.push(len);
+        payload.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+
+    struct Reader<'a> {
+        data: &'a [u8],
+        at: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+            let bytes = self
+                .data
+                .get(self.at..self.at + len)
+                .ok_or(DecodeError::TooShort {
+                    expected: self.at + len,
+                    actual: self.data.len(),
+                })?;
+            self.at += len;
+            Ok(bytes)
+        }
+
+        fn u8(&mut self) -> Result<u8, DecodeError> {
+            Ok(self.bytes(1)?[0])
+        }
+
+        fn u16(&mut self) -> Result<u16, DecodeError> {
+            Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+        }
+
+        fn u32(&mut self) -> Result<u32, DecodeError> {
+            Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+        }
+
+        fn u64(&mut self) -> Result<u64, DecodeError> {
+            Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+        }
+
+        fn string(&mut self) -> Result<String, DecodeError> {
+            let len = self.u8()? as usize;
+            Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+        }
+
+        fn address(&mut self) -> Result<(String, u16), DecodeError> {
+            Ok((self.string()?, self.u16()?))
+        }
+
+        fn records(&mut self) -> Result<Vec<Record>, DecodeError> {
+            (0..self.u32()?).map(|_| Record::decode(self)).collect()
+        }
+    }
+}
+
+pub mod cacher {
+    use super::DecodeError;
+
+    pub type Response = super::LookupResponse;
+
+    /// Leads an `Invalidate` request. Names never start with it, so plain
+    /// `Resolve` requests stay unprefixed.
+    const INVALIDATE: u8 = 0;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Request {
+        /// Look up a complete FQDN in the cache.
+        Resolve { fqdn: String },
+        /// Report that the cached entry for `fqdn` looks outdated, so the
+        /// cacher checks it against the DNS provider again.
+        Invalidate { fqdn: String },
+    }
+
+    impl Request {
+        pub fn encode(&self) -> Vec<u8> {
+            match self {
+                Request::Resolve { fqdn } => fqdn.as_bytes().to_vec(),
+                Request::Invalidate { fqdn } => {
+                    let mut payload = Vec::with_capacity(1 + fqdn.len());
+                    payload.push(INVALIDATE);
+                    payload.extend_from_slice(fqdn.as_bytes());
+                    payload
+                }
+            }
+        }
+
+        pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+            match data {
+                [] => Err(DecodeError::TooShort {
+                    expected: 1,
+                    actual: 0,
+                }),
+                [INVALIDATE] => Err(DecodeError::TooShort {
+                    expected: 2,
+                    actual: 1,
+                }),
+                [INVALIDATE, fqdn @ ..] => Ok(Request::Invalidate {
+                    fqdn: String::from_utf8_lossy(fqdn).into_owned(),
+                }),
+                fqdn => Ok(Request::Resolve {
+                    fqdn: String::from_utf8_lossy(fqdn).into_owned(),
+                }),
+            }
+        }
+    }
+}
+
+pub mod content {
+    use super::{DecodeError, EncodeError, STACK_LEN, read_status};
+    use crate::Status;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Request {
+        /// Fetch the page at `path`, which may end in `?query`, rendered with
+        /// the first of `stacks` the server supports.
+        Fetch { stacks: Vec<String>, path: String },
+    }
+
+    impl Request {
+        /// Fails if a stack is not `STACK_LEN` bytes, since the server reads
+        /// them in fixed-size chunks.
+        pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+            match self {
+                Request::Fetch { stacks, path } => {
+                    let mut payload = Vec::new();
+                    for stack in stacks {
+                        if stack.len() != STACK_LEN {
+                            return Err(EncodeError::WrongLength {
+                                field: "stack",
+                                expected: STACK_LEN,
+                                actual: stack.len(),
+                            });
+                        }
+                        payload.extend_from_slice(stack.as_bytes());
+                    }
+                    payload.push(b'/');
+                    payload.extend_from_slice(path.as_bytes());
+                    Ok(payload)
+                }
+            }
+        }
+
+        pub fn decode(mut data: &[u8]) -> Result<Self, DecodeError> {
+            let mut stacks = Vec::new();
+            loop {
+                if data.len() < STACK_LEN {
+                    return Err(DecodeError::TooShort {
+                        expected: STACK_LEN,
+                        actual: data.len(),
+                    });
+                }
+                stacks.push(String::from_utf8_lossy(&data[..STACK_LEN]).into_owned());
+                data = &data[STACK_LEN..];
+                match data.first() {
+                    None => return Err(DecodeError::Malformed("stack list is not terminated")),
+                    Some(b'/') => break,
+                    Some(_) => {}
+                }
+            }
+            Ok(Request::Fetch {
+                stacks,
+                path: String::from_utf8_lossy(&data[1..]).into_owned(),
+            })
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Response {
+        Status(Status),
+        Page { stack: String, body: Vec<u8> },
+    }
+
+    impl Response {
+        pub fn encode(&self) -> Vec<u8> {
+            match self {
+                Response::Status(status) => status.code().to_le_bytes().to_vec(),
+                Response::Page { stack, body } => {
+                    // Servers answer with one of the stacks the client sent,
+                    // which `Request::decode` cut to length.
+                    debug_assert_eq!(stack.len(), STACK_LEN, "stack {stack:?}");
+                    let mut payload = Status::Success.code().to_le_bytes().to_vec();
+                    payload.extend_from_slice(stack.as_bytes());
+                    payload.extend_from_slice(body);
+                    payload
+                }
+            }
+        }
+
+        pub fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+            let status = read_status(data)?;
+            if data.len() == 4 || status != Status::Success {
+                return Ok(Response::Status(status));
+            }
+            if data.len() < 4 + STACK_LEN {
+                return Err(DecodeError::TooShort {
+                    expected: 4 + STACK_LEN,
+                    actual: data.len(),
+                });
+            }
+            Ok(Response::Page {
+                stack: String::from_utf8_lossy(&data[4..4 + STACK_LEN]).into_owned(),
+                body: data[4 + STACK_LEN..].to_vec(),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_round_trip() {
+        let mut payload = Vec::new();
+        Version::new(1, 22, 333).encode(&mut payload);
+        payload.push(9);
+        assert_eq!(
+            Version::decode(&payload),
+            Ok((Version::new(1, 22, 333), &[9][..]))
+        );
+        assert_eq!("1.22.333".parse(), Ok(Version::new(1, 22, 333)));
+        assert!("1.2".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn lookup_response_round_trip() {
+        let responses = [
+            LookupResponse::Status(Status::NotFound),
+            LookupResponse::Address {
+                status: Status::Found,
+                address: "10.0.0.1:6202".to_owned(),
+                ttl: Some(60),
+            },
+            LookupResponse::Address {
+                status: Status::Success,
+                address: "10.0.0.2:80".to_owned(),
+                ttl: None,
+            },
+        ];
+        for response in responses {
+            let with_ttl = response.clone().encode(true);
+            assert_eq!(
+                LookupResponse::decode(&with_ttl, true),
+                Ok(response.clone())
+            );
+            // Without the capability the ttl is not sent at all.
+            let without = match response.clone() {
+                LookupResponse::Address {
+                    status, address, ..
+                } => LookupResponse::Address {
+                    status,
+                    address,
+                    ttl: None,
+                },
+                status => status,
+            };
+            assert_eq!(
+                LookupResponse::decode(&response.encode(false), false),
+                Ok(without)
+            );
+        }
+    }
+
+    #[test]
+    fn provider_request_round_trip() {
+        let requests = [
+            provider::Request::Resolve {
+                last_block: true,
+                block: "example".to_owned(),
+            },
+            provider::Request::Resolve {
+                last_block: false,
+                block: "com".to_owned(),
+            },
+            provider::Request::Manage(signed(admin::Command::List)),
+        ];
+        for request in requests {
+            assert_eq!(
+                provider::Request::decode(&request.encode().unwrap()),
+                Ok(request)
+            );
+        }
+        assert!(provider::Request::decode(&[7, b'x']).is_err());
+    }
+
+    fn signed(command: admin::Command) -> admin::Signed {
+        admin::Signed {
+            key_id: "admin".to_owned(),
+            timestamp: 1_700_000_000,
+            nonce: [7; admin::NONCE_LEN],
+            command,
+            mac: vec![1; admin::MAC_LEN],
+        }
+    }
+
+    fn record(name: &str, dns: usize) -> admin::Record {
+        admin::Record {
+            name: name.to_owned(),
+            domain: Some(("10.0.0.1".to_owned(), 6204)),
+            dns: (0..dns)
+                .map(|port| ("10.0.1.1".to_owned(), 6202 + port as u16))
+                .collect(),
+            ttl: 300,
+        }
+    }
+
+    #[test]
+    fn admin_signed_round_trip() {
+        let commands = [
+            admin::Command::List,
+            admin::Command::Get {
+                name: "example".to_owned(),
+            },
+            admin::Command::Put(record("shop", 2)),
+            admin::Command::Put(admin::Record {
+                domain: None,
+                ..record(".", 1)
+            }),
+            admin::Command::Delete {
+                name: "shop".to_owned(),
+            },
+            admin::Command::Transfer { since: 42 },
+        ];
+        for command in commands {
+            let signed = signed(command);
+            assert_eq!(admin::Signed::decode(&signed.encode().unwrap()), Ok(signed));
+        }
+    }
+
+    #[test]
+    fn admin_signed_rejects_trailing_bytes() {
+        let signed = signed(admin::Command::List);
+        let mut payload = signed.body().unwrap();
+        payload.push(0);
+        payload.extend_from_slice(&signed.mac);
+        assert!(admin::Signed::decode(&payload).is_err());
+        assert!(admin::Signed::decode(&[0; 10]).is_err());
+    }
+
+    #[test]
+    fn admin_response_round_trip() {
+        let responses = [
+            admin::Response::Status(Status::Forbidden),
+            admin::Response::Records(vec![]),
+            admin::Response::Records(vec![record("example", 0), record("shop", 3)]),
+            admin::Response::Transfer(admin::Transfer {
+                serial: 9,
+                full: false,
+                records: vec![record("shop", 1)],
+                deleted: vec!["example".to_owned()],
+            }),
+        ];
+        for response in responses {
+            assert_eq!(
+                admin::Response::decode(&response.encode().unwrap()),
+                Ok(response)
+            );
+        }
+    }
+
+    #[test]
+    fn admin_rejects_oversize_fields() {
+        let too_long = |field, actual| {
+            Err(EncodeError::TooLong {
+                field,
+                max: 255,
+                actual,
+            })
+        };
+        let long_name = "x".repeat(256);
+        assert_eq!(
+            signed(admin::Command::Put(record(&long_name, 0))).encode(),
+            too_long("name", 256)
+        );
+        assert_eq!(
+            signed(admin::Command::Put(record("shop", 256))).encode(),
+            too_long("dns", 256)
+        );
+        let mut long_address = record("shop", 0);
+        long_address.domain = Some(("1".repeat(300), 80));
+        assert_eq!(
+            admin::Response::Records(vec![long_address]).encode(),
+            too_long("address", 300)
+        );
+        assert_eq!(
+            admin::Signed {
+                key_id: long_name.clone(),
+                ..signed(admin::Command::List)
+            }
+            .body(),
+            too_long("key id", 256)
+        );
+        assert_eq!(
+            provider::Request::Manage(signed(admin::Command::Delete { name: long_name })).encode(),
+            too_long("name", 256)
+        );
+        // The largest values that fit still round-trip.
+        let command = admin::Command::Put(record(&"x".repeat(255), 255));
+        let signed = signed(command);
+        assert_eq!(admin::Signed::decode(&signed.encode().unwrap()), Ok(signed));
+    }
+
+    #[test]
+    fn cacher_request_round_trip() {
+        let requests = [
+            cacher::Request::Resolve {
+                fqdn: "page.example.com".to_owned(),
+            },
+            cacher::Request::Invalidate {
+                fqdn: "page.example.com".to_owned(),
+            },
+        ];
+        for request in requests {
+            assert_eq!(cacher::Request::decode(&request.encode()), Ok(request));
+        }
+        assert!(cacher::Request::decode(&[]).is_err());
+        assert!(cacher::Request::decode(&[0]).is_err());
+    }
+
+    #[test]
+    fn content_request_round_trip() {
+        let request = content::Request::Fetch {
+            stacks: vec!["mdprs".to_owned(), "htmlx".to_owned()],
+            path: "docs/index".to_owned(),
+        };
+        assert_eq!(
+            content::Request::decode(&request.encode().unwrap()),
+            Ok(request)
+        );
+    }
+
+    #[test]
+    fn content_request_rejects_wrong_stack_length() {
+        for stack in ["mdp", "mdparser"] {
+            let request = content::Request::Fetch {
+                stacks: vec!["mdprs".to_owned(), stack.to_owned()],
+                path: "page".to_owned(),
+            };
+            assert_eq!(
+                request.encode(),
+                Err(EncodeError::WrongLength {
+                    field: "stack",
+                    expected: STACK_LEN,
+                    actual: stack.len(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn content_response_round_trip() {
+        let responses = [
+            content::Response::Status(Status::NotFound),
+            content::Response::Page {
+                stack: "mdprs".to_owned(),
+                body: b"# Hello".to_vec(),
+            },
+        ];
+        for response in responses {
+            assert_eq!(content::Response::decode(&response.encode()), Ok(response));
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+
+/// Status code carried in the first four bytes of every response.
+///
+/// Codes follow the HTTP convention of grouping by hundreds, so unknown codes
+/// can still be categorised. Codes without a named variant are kept as
+/// `Unknown` and round-trip unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    TestNotImplemented,
+    Success,
+    Created,
+    NonAuthoritative,
+    PermanentRedirect,
+    Found,
+    BadRequest,
+    Unauthorized,
+    TooSmall,
+    Forbidden,
+    NotFound,
+    Gone,
+    TooLarge,
+    Misdirected,
+    Unprocessable,
+    UpgradeRequired,
+    DowngradeRequired,
+    HostUnreachable,
+    ShatTheBed,
+    NotImplemented,
+    TimedOut,
+    LoopDetected,
+    BadResponse,
+    Unknown(u32),
+}
+
+impl Status {
+    pub fn code(self) -> u32 {
+        self.into()
+    }
+
+    pub fn is_success(self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    pub fn is_redirect(self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    pub fn is_client_error(self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    pub fn is_server_error(self) -> bool {
+        (500..600).contains(&self.code())
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Status::TestNotImplemented => "[TEST] Not implemented.",
+            Status::Success => "Server completed request successfully.",
+            Status::Created => "Resource created.",
+            Status::NonAuthoritative => "Response doesn't resemble intended data.",
+            Status::PermanentRedirect => "Server has moved.",
+            Status::Found => "Server expected additional requests.",
+            Status::BadRequest => "Bad request.",
+            Status::Unauthorized => "Authentication failed.",
+            Status::TooSmall => "Payload too small.",
+            Status::Forbidden => "Forbidden action.",
+            Status::NotFound => "Resource not found.",
+            Status::Gone => "Client expected additional requests.",
+            Status::TooLarge => "Payload too large.",
+            Status::Misdirected => "Server could not complete task.",
+            Status::Unprocessable => "Unprocessable request.",
+            Status::UpgradeRequired => "Client program upgrade required.",
+            Status::DowngradeRequired => "Client program downgrade required.",
+            Status::HostUnreachable => "No route to host",
+            Status::ShatTheBed => "Client program reached an invalid state.",
+            Status::NotImplemented => "Operation not implemented.",
+            Status::TimedOut => "Server did not answer in time.",
+            Status::LoopDetected => "Redirect loop detected.",
+            Status::BadResponse => "Server sent unexpected response.",
+            Status::Unknown(_) => "Communication fault.",
+        }
+    }
+}
+
+impl From<u32> for Status {
+    fn from(code: u32) -> Self {
+        match code {
+            0 => Status::TestNotImplemented,
+            200 => Status::Success,
+            201 => Status::Created,
+            203 => Status::NonAuthoritative,
+            301 => Status::PermanentRedirect,
+            302 => Status::Found,
+            400 => Status::BadRequest,
+            401 => Status::Unauthorized,
+            402 => Status::TooSmall,
+            403 => Status::Forbidden,
+            404 => Status::NotFound,
+            410 => Status::Gone,
+            413 => Status::TooLarge,
+            421 => Status::Misdirected,
+            422 => Status::Unprocessable,
+            426 => Status::UpgradeRequired,
+            427 => Status::DowngradeRequired,
+            432 => Status::HostUnreachable,
+            433 => Status::ShatTheBed,
+            501 => Status::NotImplemented,
+            504 => Status::TimedOut,
+            508 => Status::LoopDetected,
+            512 => Status::BadResponse,
+            code => Status::Unknown(code),
+        }
+    }
+}
+
+impl From<Status> for u32 {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::TestNotImplemented => 0,
+            Status::Success => 200,
+            Status::Created => 201,
+            Status::NonAuthoritative => 203,
+            Status::PermanentRedirect => 301,
+            Status::Found => 302,
+            Status::BadRequest => 400,
+            Status::Unauthorized => 401,
+            Status::TooSmall => 402,
+            Status::Forbidden => 403,
+            Status::NotFound => 404,
+            Status::Gone => 410,
+            Status::TooLarge => 413,
+            Status::Misdirected => 421,
+            Status::Unprocessable => 422,
+            Status::UpgradeRequired => 426,
+            Status::DowngradeRequired => 427,
+            Status::HostUnreachable => 432,
+            Status::ShatTheBed => 433,
+            Status::NotImplemented => 501,
+            Status::TimedOut => 504,
+            Status::LoopDetected => 508,
+            Status::BadResponse => 512,
+            Status::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code(), self.description())
+    }
+}
+
+impl std::error::Error for Status {}
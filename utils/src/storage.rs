@@ -0,0 +1,112 @@
+//! Databases the DNS daemons keep their records in.
+//!
+//! Either daemon runs on MySQL or SQLite, picked by the scheme of its
+//! connection URL. Queries that read the same in both dialects are written
+//! once with `with_pool!`; the rest match on `Storage` themselves.
+//!
+//! A `Storage` is a connection pool. Each daemon connects once at startup and
+//! clones it into every connection task; clones share the same pool.
+
+use sqlx::{
+    mysql::{MySqlPool, MySqlPoolOptions},
+    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
+};
+use std::{fmt, str::FromStr, time::Duration};
+
+#[derive(Debug, Clone)]
+pub enum Storage {
+    MySql(MySqlPool),
+    Sqlite(SqlitePool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    MySql,
+    Sqlite,
+}
+
+/// How many connections a `Storage` keeps and how long a query waits for one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    /// How long to wait for a free connection before giving up.
+    pub acquire_timeout: Duration,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        PoolSettings {
+            max_connections: 10,
+            acquire_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Dialect {
+    /// The dialect of a `mysql://` or `sqlite://` connection URL.
+    pub fn of(url: &str) -> Option<Self> {
+        match url.split_once(':')?.0 {
+            "mysql" | "mariadb" => Some(Dialect::MySql),
+            "sqlite" => Some(Dialect::Sqlite),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::MySql => write!(f, "MySQL"),
+            Dialect::Sqlite => write!(f, "SQLite"),
+        }
+    }
+}
+
+impl Storage {
+    /// Opens a pool of connections to the database at `url`. A SQLite file is
+    /// created if it does not exist yet, so that `--overwrite` can set it up.
+    pub async fn connect(url: &str, settings: &PoolSettings) -> Result<Self, sqlx::Error> {
+        match Dialect::of(url) {
+            Some(Dialect::MySql) => Ok(Storage::MySql(
+                MySqlPoolOptions::new()
+                    .max_connections(settings.max_connections)
+                    .acquire_timeout(settings.acquire_timeout)
+                    .connect(url)
+                    .await?,
+            )),
+            Some(Dialect::Sqlite) => {
+                let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+                Ok(Storage::Sqlite(
+                    SqlitePoolOptions::new()
+                        .max_connections(settings.max_connections)
+                        .acquire_timeout(settings.acquire_timeout)
+                        .connect_with(options)
+                        .await?,
+                ))
+            }
+            None => Err(sqlx::Error::Configuration(
+                "expected a mysql:// or sqlite:// URL".into(),
+            )),
+        }
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        match self {
+            Storage::MySql(_) => Dialect::MySql,
+            Storage::Sqlite(_) => Dialect::Sqlite,
+        }
+    }
+}
+
+/// Evaluates `$body` with `$pool` bound to the pool inside `$storage`,
+/// whichever database that is. `$body` is compiled once per database, so it
+/// may use query builders generic over the database type.
+#[macro_export]
+macro_rules! with_pool {
+    ($storage:expr, |$pool:ident| $body:expr) => {
+        match $storage {
+            $crate::storage::Storage::MySql($pool) => $body,
+            $crate::storage::Storage::Sqlite($pool) => $body,
+        }
+    };
+}
@@ -0,0 +1,241 @@
+//! Optional TLS layer underneath the framing.
+//!
+//! Servers enable it by loading a certificate chain and private key into a
+//! `TlsAcceptor`; clients by building a `TlsConnector` from a trust store of
+//! PEM certificates and passing it to `connect` for the peers that use TLS.
+//! Either way the rest of the program only sees a `Stream`, which is plain
+//! TCP when TLS is off. Certificates are checked against the host part of the
+//! address being dialled, so a self-signed certificate for a bare IP needs
+//! that IP in its subject alternative names. It must also not be marked as a
+//! CA, e.g. for local testing:
+//!
+//! ```text
+//! openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:prime256v1 -nodes \
+//!     -keyout key.pem -out cert.pem -days 30 -subj "/CN=localhost" \
+//!     -addext "subjectAltName=IP:127.0.0.1,DNS:localhost" \
+//!     -addext "basicConstraints=critical,CA:FALSE"
+//! ```
+//!
+//! The same `cert.pem` is then passed to the server as `--tls-cert` and to the
+//! frontend as `--tls-ca`.
+
+use crate::framing::FrameLimits;
+use async_std::{io as async_io, net::TcpStream};
+use futures::{AsyncRead, AsyncWrite};
+use futures_rustls::{TlsAcceptor, TlsConnector, client, server};
+use rustls::{
+    ClientConfig, RootCertStore, ServerConfig,
+    crypto::{CryptoProvider, ring},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+};
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tracing::debug;
+
+/// A connection to a peer, encrypted or not.
+pub enum Stream {
+    Plain(TcpStream),
+    Client(Box<client::TlsStream<TcpStream>>),
+    Server(Box<server::TlsStream<TcpStream>>),
+}
+
+impl Stream {
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Stream::Plain(stream) => stream.peer_addr(),
+            Stream::Client(stream) => stream.get_ref().0.peer_addr(),
+            Stream::Server(stream) => stream.get_ref().0.peer_addr(),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        !matches!(self, Stream::Plain(_))
+    }
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Client(stream) => Pin::new(stream).poll_read(cx, buf),
+            Stream::Server(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Client(stream) => Pin::new(stream).poll_write(cx, buf),
+            Stream::Server(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Client(stream) => Pin::new(stream).poll_flush(cx),
+            Stream::Server(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Stream::Plain(stream) => Pin::new(stream).poll_close(cx),
+            Stream::Client(stream) => Pin::new(stream).poll_close(cx),
+            Stream::Server(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    /// A PEM file could not be read or parsed.
+    Pem {
+        path: PathBuf,
+        source: rustls::pki_types::pem::Error,
+    },
+    /// A PEM file was readable but held no certificates.
+    NoCertificates(PathBuf),
+    Rustls(rustls::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Pem { path, source } => {
+                write!(f, "failed to load {}: {}", path.display(), source)
+            }
+            TlsError::NoCertificates(path) => {
+                write!(f, "no certificates found in {}", path.display())
+            }
+            TlsError::Rustls(e) => write!(f, "TLS configuration rejected: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TlsError::Pem { source, .. } => Some(source),
+            TlsError::NoCertificates(_) => None,
+            TlsError::Rustls(e) => Some(e),
+        }
+    }
+}
+
+impl From<rustls::Error> for TlsError {
+    fn from(e: rustls::Error) -> Self {
+        TlsError::Rustls(e)
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let pem_error = |source| TlsError::Pem {
+        path: path.to_path_buf(),
+        source,
+    };
+    let certificates = CertificateDer::pem_file_iter(path)
+        .map_err(pem_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pem_error)?;
+    if certificates.is_empty() {
+        return Err(TlsError::NoCertificates(path.to_path_buf()));
+    }
+    Ok(certificates)
+}
+
+/// Builds the server side from a PEM certificate chain and private key.
+pub fn server_acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor, TlsError> {
+    let chain = load_certificates(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|source| TlsError::Pem {
+        path: key.to_path_buf(),
+        source,
+    })?;
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Builds the client side, trusting only the certificates in `trust_store`.
+pub fn client_connector(trust_store: &Path) -> Result<TlsConnector, TlsError> {
+    let mut roots = RootCertStore::empty();
+    for certificate in load_certificates(trust_store)? {
+        roots.add(certificate)?;
+    }
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Opens a connection to `address` (`host:port`), wrapped in TLS through
+/// `connector` when one is given.
+pub async fn connect(
+    address: &str,
+    connector: Option<&TlsConnector>,
+    limits: &FrameLimits,
+) -> io::Result<Stream> {
+    let stream = TcpStream::connect(address).await?;
+    let Some(connector) = connector else {
+        return Ok(Stream::Plain(stream));
+    };
+    let host = address
+        .rsplit_once(':')
+        .map_or(address, |(host, _)| host)
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let name = ServerName::try_from(host.to_owned())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let stream = within(limits, connector.connect(name, stream)).await?;
+    debug!("TLS session established with {}", address);
+    Ok(Stream::Client(Box::new(stream)))
+}
+
+/// Completes the server side of a freshly accepted connection, performing the
+/// TLS handshake when an acceptor is configured.
+pub async fn accept(
+    stream: TcpStream,
+    acceptor: Option<&TlsAcceptor>,
+    limits: &FrameLimits,
+) -> io::Result<Stream> {
+    match acceptor {
+        None => Ok(Stream::Plain(stream)),
+        Some(acceptor) => {
+            let stream = within(limits, acceptor.accept(stream)).await?;
+            Ok(Stream::Server(Box::new(stream)))
+        }
+    }
+}
+
+async fn within<T>(
+    limits: &FrameLimits,
+    future: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match limits.read_timeout {
+        Some(timeout) => async_io::timeout(timeout, future).await,
+        None => future.await,
+    }
+}
@@ -0,0 +1,400 @@
+//! `web://` URLs.
+//!
+//! A `WebUrl` is always absolute and normalised: the scheme and host are
+//! lowercase, a trailing dot on the host is dropped, and the path is rooted
+//! with `.` and `..` segments resolved. Its `Display` form is therefore the
+//! canonical key for history, caching and links, and parses back to an equal
+//! value. Input without a scheme is taken to be `web://`.
+
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+pub const SCHEME: &str = "web";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Host {
+    Domain(String),
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+}
+
+impl Host {
+    fn parse(host: &str) -> Result<Self, UrlError> {
+        if host.is_empty() {
+            return Err(UrlError::EmptyHost);
+        }
+        if let Some(inner) = host.strip_prefix('[') {
+            return inner
+                .strip_suffix(']')
+                .and_then(|inner| inner.parse().ok())
+                .map(Host::Ipv6)
+                .ok_or_else(|| UrlError::InvalidHost(host.to_owned()));
+        }
+        if let Ok(ip) = host.parse() {
+            return Ok(Host::Ipv4(ip));
+        }
+        let domain = host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase();
+        let valid_label = |label: &str| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        };
+        if domain.len() > 253 || !domain.split('.').all(valid_label) {
+            return Err(UrlError::InvalidHost(host.to_owned()));
+        }
+        Ok(Host::Domain(domain))
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Domain(domain) => write!(f, "{domain}"),
+            Host::Ipv4(ip) => write!(f, "{ip}"),
+            Host::Ipv6(ip) => write!(f, "[{ip}]"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WebUrl {
+    host: Host,
+    port: Option<u16>,
+    path: String,
+    query: Option<String>,
+    fragment: Option<String>,
+}
+
+impl WebUrl {
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// Always starts with `/`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// The path and query, as a content server is asked for them. The
+    /// fragment stays with the client.
+    pub fn target(&self) -> String {
+        match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone(),
+        }
+    }
+
+    /// Resolves `reference` against this URL the way a hyperlink on this page
+    /// would be followed, e.g. `../img.md`, `/about`, `?page=2` or `#intro`.
+    pub fn join(&self, reference: &str) -> Result<WebUrl, UrlError> {
+        check_characters(reference)?;
+        if split_scheme(reference)?.is_some() {
+            return reference.parse();
+        }
+        if let Some(rest) = reference.strip_prefix("//") {
+            return parse_hierarchy(rest);
+        }
+        let (path, query, fragment) = split_tail(reference);
+        let mut url = self.clone();
+        url.fragment = fragment.map(str::to_owned);
+        if path.is_empty() {
+            if query.is_some() {
+                url.query = query.map(str::to_owned);
+            }
+            return Ok(url);
+        }
+        url.path = if path.starts_with('/') {
+            normalise_path(path)
+        } else {
+            let directory = &self.path[..=self.path.rfind('/').unwrap_or(0)];
+            normalise_path(&format!("{directory}{path}"))
+        };
+        url.query = query.map(str::to_owned);
+        Ok(url)
+    }
+}
+
+impl FromStr for WebUrl {
+    type Err = UrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(UrlError::Empty);
+        }
+        check_characters(s)?;
+        match split_scheme(s)? {
+            Some(rest) => parse_hierarchy(rest),
+            None => parse_hierarchy(s),
+        }
+    }
+}
+
+impl fmt::Display for WebUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", SCHEME, self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        write!(f, "{}", self.path)?;
+        if let Some(query) = &self.query {
+            write!(f, "?{query}")?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{fragment}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+    Empty,
+    UnsupportedScheme(String),
+    /// Whitespace and control characters are never valid in a URL.
+    InvalidCharacter(char),
+    EmptyHost,
+    InvalidHost(String),
+    InvalidPort(String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlError::Empty => write!(f, "URL is empty"),
+            UrlError::UnsupportedScheme(scheme) => {
+                write!(f, "unsupported scheme {scheme:?}, expected {SCHEME:?}")
+            }
+            UrlError::InvalidCharacter(c) => write!(f, "invalid character {c:?} in URL"),
+            UrlError::EmptyHost => write!(f, "URL has no host"),
+            UrlError::InvalidHost(host) => write!(f, "invalid host {host:?}"),
+            UrlError::InvalidPort(port) => write!(f, "invalid port {port:?}"),
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+fn check_characters(s: &str) -> Result<(), UrlError> {
+    match s.chars().find(|c| c.is_whitespace() || c.is_control()) {
+        Some(c) => Err(UrlError::InvalidCharacter(c)),
+        None => Ok(()),
+    }
+}
+
+/// Returns what follows `scheme://` if `s` starts with a scheme, which must be
+/// `web`.
+fn split_scheme(s: &str) -> Result<Option<&str>, UrlError> {
+    let Some((scheme, rest)) = s.split_once("://") else {
+        return Ok(None);
+    };
+    let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !is_scheme {
+        return Ok(None);
+    }
+    if !scheme.eq_ignore_ascii_case(SCHEME) {
+        return Err(UrlError::UnsupportedScheme(scheme.to_owned()));
+    }
+    Ok(Some(rest))
+}
+
+/// Parses `host[:port][/path][?query][#fragment]`.
+fn parse_hierarchy(s: &str) -> Result<WebUrl, UrlError> {
+    let end = s.find(['/', '?', '#']).unwrap_or(s.len());
+    let (authority, tail) = s.split_at(end);
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(_) => match authority.find(']') {
+            Some(close) => (&authority[..=close], &authority[close + 1..]),
+            None => return Err(UrlError::InvalidHost(authority.to_owned())),
+        },
+        None => authority.split_at(authority.find(':').unwrap_or(authority.len())),
+    };
+    let port = match port {
+        "" => None,
+        port => match port.strip_prefix(':').map(str::parse) {
+            Some(Ok(port)) => Some(port),
+            _ => {
+                return Err(UrlError::InvalidPort(
+                    port.trim_start_matches(':').to_owned(),
+                ));
+            }
+        },
+    };
+    let (path, query, fragment) = split_tail(tail);
+    Ok(WebUrl {
+        host: Host::parse(host)?,
+        port,
+        path: normalise_path(path),
+        query: query.map(str::to_owned),
+        fragment: fragment.map(str::to_owned),
+    })
+}
+
+fn split_tail(tail: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (rest, fragment) = match tail.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (tail, None),
+    };
+    match rest.split_once('?') {
+        Some((path, query)) => (path, Some(query), fragment),
+        None => (rest, None, fragment),
+    }
+}
+
+/// Roots `path` and resolves its `.` and `..` segments. Empty segments are
+/// dropped, but a trailing slash is kept.
+fn normalise_path(path: &str) -> String {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let trailing_slash = path.ends_with('/') || path.ends_with("/.") || path.ends_with("/..");
+    let mut normalised = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalised.push('/');
+    }
+    normalised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> WebUrl {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_every_part() {
+        let parsed = url("web://example.com:6204/docs/page.md?lang=en#intro");
+        assert_eq!(parsed.host(), &Host::Domain("example.com".to_owned()));
+        assert_eq!(parsed.port(), Some(6204));
+        assert_eq!(parsed.path(), "/docs/page.md");
+        assert_eq!(parsed.query(), Some("lang=en"));
+        assert_eq!(parsed.fragment(), Some("intro"));
+        assert_eq!(parsed.target(), "/docs/page.md?lang=en");
+    }
+
+    #[test]
+    fn parses_ip_hosts_and_bare_input() {
+        let v4 = url("127.0.0.1:8080");
+        assert_eq!(v4.host(), &Host::Ipv4(Ipv4Addr::LOCALHOST));
+        assert_eq!(v4.port(), Some(8080));
+        assert_eq!(v4.path(), "/");
+        let v6 = url("web://[::1]:6204/a");
+        assert_eq!(v6.host(), &Host::Ipv6(Ipv6Addr::LOCALHOST));
+        assert_eq!(v6.port(), Some(6204));
+        assert_eq!(v6.to_string(), "web://[::1]:6204/a");
+        assert_eq!(url("  example.com  ").to_string(), "web://example.com/");
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!("".parse::<WebUrl>(), Err(UrlError::Empty));
+        assert_eq!(
+            "http://example.com".parse::<WebUrl>(),
+            Err(UrlError::UnsupportedScheme("http".to_owned()))
+        );
+        assert_eq!(
+            "web://exa mple.com".parse::<WebUrl>(),
+            Err(UrlError::InvalidCharacter(' '))
+        );
+        assert_eq!("web:///path".parse::<WebUrl>(), Err(UrlError::EmptyHost));
+        assert_eq!(
+            "web://-bad-.com".parse::<WebUrl>(),
+            Err(UrlError::InvalidHost("-bad-.com".to_owned()))
+        );
+        assert_eq!(
+            "web://[::1/".parse::<WebUrl>(),
+            Err(UrlError::InvalidHost("[::1".to_owned()))
+        );
+        assert_eq!(
+            "web://example.com:99999".parse::<WebUrl>(),
+            Err(UrlError::InvalidPort("99999".to_owned()))
+        );
+        assert_eq!(
+            "web://example.com:".parse::<WebUrl>(),
+            Err(UrlError::InvalidPort(String::new()))
+        );
+    }
+
+    #[test]
+    fn normalises() {
+        assert_eq!(url("WEB://Example.COM.").to_string(), "web://example.com/");
+        assert_eq!(url("example.com/a/./b/../c").path(), "/a/c");
+        assert_eq!(url("example.com/a//b/").path(), "/a/b/");
+        assert_eq!(url("example.com/a/..").path(), "/");
+        assert_eq!(url("example.com/../../a").path(), "/a");
+        assert_eq!(url("example.com/a/b/..").path(), "/a/");
+        assert_eq!(url("web://Example.com/x"), url("example.com./x"));
+    }
+
+    #[test]
+    fn joins_relative_references() {
+        let base = url("web://example.com/docs/guide/page.md?lang=en#top");
+        let joined = |reference| base.join(reference).unwrap().to_string();
+        assert_eq!(joined("other.md"), "web://example.com/docs/guide/other.md");
+        assert_eq!(joined("../img.md"), "web://example.com/docs/img.md");
+        assert_eq!(joined("/about"), "web://example.com/about");
+        assert_eq!(
+            joined("?page=2"),
+            "web://example.com/docs/guide/page.md?page=2"
+        );
+        assert_eq!(
+            joined("#intro"),
+            "web://example.com/docs/guide/page.md?lang=en#intro"
+        );
+        assert_eq!(joined("//other.org/x"), "web://other.org/x");
+        assert_eq!(joined("web://other.org"), "web://other.org/");
+        assert_eq!(
+            base.join("http://other.org"),
+            Err(UrlError::UnsupportedScheme("http".to_owned()))
+        );
+        assert_eq!(base.join("a b"), Err(UrlError::InvalidCharacter(' ')));
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for input in [
+            "web://example.com/",
+            "web://example.com:6204/docs/page.md?lang=en#intro",
+            "web://10.0.0.1/a/b/",
+            "web://[2001:db8::1]:80/?q",
+            "web://example.com/#",
+        ] {
+            let parsed = url(input);
+            assert_eq!(parsed.to_string(), input);
+            assert_eq!(url(&parsed.to_string()), parsed);
+        }
+        let messy = url("Example.COM./a/../b?x#y");
+        assert_eq!(url(&messy.to_string()), messy);
+    }
+}
@@ -0,0 +1,207 @@
+use backend::{Hop, Resolution, ResolverConfig, resolve};
+use serde_json::{Value, json};
+use std::{
+    path,
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+use tracing::level_filters::LevelFilter;
+use utils::{
+    Status,
+    config::{Config, Opt, Spec},
+    tls,
+};
+
+const SPEC: Spec = Spec {
+    program: "webdig",
+    about: "Resolves names through DNS cachers and providers and shows every step.",
+    applet: "webdig",
+    env_prefix: "WEBDIG",
+    options: &[
+        Opt::operands("name", "NAME", "Names or URLs to resolve"),
+        Opt::list(
+            "provider",
+            Some('p'),
+            "ADDRESS",
+            "Root DNS providers to ask, in order",
+        )
+        .default("0.0.0.0:6202"),
+        Opt::list(
+            "cacher",
+            Some('c'),
+            "ADDRESS",
+            "DNS cachers to ask, in order",
+        )
+        .default("0.0.0.0:6203"),
+        Opt::flag("provider-only", Some('P'), "Ask only the DNS providers"),
+        Opt::flag("cacher-only", Some('C'), "Ask only the DNS cachers"),
+        Opt::value(
+            "strategy",
+            None,
+            "STRATEGY",
+            "Use several providers or cachers by failover or in parallel",
+        )
+        .default("failover"),
+        Opt::value(
+            "timeout",
+            Some('t'),
+            "SECONDS",
+            "Give up on a name after this long",
+        )
+        .default("15"),
+        Opt::value(
+            "tls-ca",
+            None,
+            "FILE",
+            "PEM certificates to trust; enables TLS",
+        ),
+        Opt::flag("json", Some('j'), "Print the results as JSON"),
+        Opt::count("verbose", Some('v'), "Log to stderr; repeat for more"),
+    ],
+};
+
+#[async_std::main]
+async fn main() -> ExitCode {
+    let config = Config::load_or_exit(&SPEC);
+    // The trace is the output, so logging stays off unless asked for.
+    let log_level = match config.count("verbose") {
+        0 => LevelFilter::OFF,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(log_level)
+        .with_writer(std::io::stderr)
+        .init();
+    let names = config.list("name");
+    if names.is_empty() {
+        eprintln!("webdig: no name to resolve");
+        eprintln!("Try 'webdig --help' for more information.");
+        return ExitCode::from(2);
+    }
+    let tls = match config
+        .get("tls-ca")
+        .map(path::Path::new)
+        .map(tls::client_connector)
+    {
+        None => None,
+        Some(Ok(connector)) => Some(connector),
+        Some(Err(e)) => {
+            eprintln!("webdig: failed to set up TLS: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let resolvers = match resolver_config(&config) {
+        Ok(resolvers) => ResolverConfig { tls, ..resolvers },
+        Err(e) => {
+            eprintln!("webdig: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let mut resolved = true;
+    let mut results = Vec::new();
+    for name in names {
+        let started = Instant::now();
+        let resolution = resolve(name, &resolvers).await;
+        let elapsed = started.elapsed();
+        resolved &= resolution.address.is_some();
+        if config.flag("json") {
+            results.push(to_json(name, &resolution, elapsed));
+        } else {
+            print_resolution(name, &resolution, elapsed);
+        }
+    }
+    if config.flag("json") {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&Value::Array(results)).unwrap_or_default()
+        );
+    }
+    if resolved {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn resolver_config(config: &Config) -> Result<ResolverConfig, String> {
+    let owned = |long| {
+        config
+            .list(long)
+            .into_iter()
+            .map(str::to_owned)
+            .collect::<Vec<_>>()
+    };
+    let (providers, cachers) = match (config.flag("provider-only"), config.flag("cacher-only")) {
+        (true, true) => return Err("--provider-only and --cacher-only conflict".to_owned()),
+        (true, false) => (owned("provider"), Vec::new()),
+        (false, true) => (Vec::new(), owned("cacher")),
+        (false, false) => (owned("provider"), owned("cacher")),
+    };
+    Ok(ResolverConfig {
+        providers,
+        cachers,
+        strategy: config.require("strategy").map_err(|e| e.to_string())?,
+        integrity_check: false,
+        timeout: Duration::from_secs(config.require("timeout").map_err(|e| e.to_string())?),
+        tls: None,
+    })
+}
+
+fn print_resolution(name: &str, resolution: &Resolution, elapsed: Duration) {
+    println!("; webdig {name}");
+    for hop in &resolution.hops {
+        println!(";; {} in {} ms", hop, hop.elapsed.as_millis());
+    }
+    match &resolution.address {
+        Some(address) => println!("{}\t{}\t{}", name, resolution.ttl.as_secs(), address),
+        None => println!(";; no address for {name}"),
+    }
+    println!(";; Status: {}", resolution.status);
+    let source = resolution
+        .source
+        .map_or("nobody".to_owned(), |source| source.to_string());
+    let authority = if resolution.is_authoritative() {
+        "authoritative"
+    } else {
+        "not authoritative"
+    };
+    println!(
+        ";; Answered by {}, {}, in {} ms\n",
+        source,
+        authority,
+        elapsed.as_millis()
+    );
+}
+
+fn status_json(status: Status) -> Value {
+    json!({
+        "code": status.code(),
+        "description": status.description(),
+    })
+}
+
+fn hop_json(hop: &Hop) -> Value {
+    json!({
+        "server": hop.server,
+        "name": hop.name,
+        "status": status_json(hop.status),
+        "address": hop.address,
+        "ttl": hop.ttl,
+        "elapsed_ms": hop.elapsed.as_millis() as u64,
+    })
+}
+
+fn to_json(name: &str, resolution: &Resolution, elapsed: Duration) -> Value {
+    json!({
+        "name": name,
+        "address": resolution.address,
+        "status": status_json(resolution.status),
+        "ttl": resolution.ttl.as_secs(),
+        "source": resolution.source.map(|source| source.to_string()),
+        "authoritative": resolution.is_authoritative(),
+        "elapsed_ms": elapsed.as_millis() as u64,
+        "hops": resolution.hops.iter().map(hop_json).collect::<Vec<_>>(),
+    })
+}